@@ -0,0 +1,102 @@
+use crate::vector::V3;
+use crate::color::Col3;
+
+// Settings for taking more samples only where a pixel is still noisy. Every pixel first gets the
+// camera's rays_per_pixel samples, then keeps sampling one ray at a time until the standard error
+// of its mean luminance drops below threshold times that mean, or max_samples is reached.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    pub max_samples : u16,
+    pub threshold : f32, // relative error, e.g. 0.05 stops at 5% noise
+    pub show_sample_counts : bool, // render the per pixel sample count as a heat map instead of the image
+}
+
+impl AdaptiveSampling {
+    pub fn new(max_samples : u16, threshold : f32) -> Self {
+        AdaptiveSampling {
+            max_samples,
+            threshold,
+            show_sample_counts : false,
+        }
+    }
+}
+
+// running mean and variance of one pixel's sample luminance (Welford's algorithm)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelVariance {
+    pub count : u32,
+    pub mean : f32,
+    m2 : f32,
+}
+
+impl PixelVariance {
+    pub fn add(&mut self, value : f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // unbiased sample variance
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f32
+    }
+
+    // standard error of the mean
+    pub fn error(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        f32::sqrt(self.variance() / self.count as f32)
+    }
+
+    // relative to the mean, with a floor of one 8 bit step so near black pixels don't sample forever
+    pub fn converged(&self, threshold : f32) -> bool {
+        self.count >= 2 && self.error() <= threshold * f32::max(self.mean, 1.0)
+    }
+}
+
+// rec. 709 luminance of an rgb value
+pub fn luminance(rgb : V3) -> f32 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+// blue for pixels that stopped early, through green to red for those that hit max_samples
+pub fn sample_count_color(count : u32, max_samples : u16) -> Col3 {
+    let t = f32::clamp(count as f32 / max_samples.max(1) as f32, 0.0, 1.0);
+    let ramp = |v : f32| (f32::clamp(v, 0.0, 1.0) * 255.0) as u8;
+    Col3::new(ramp(2.0 * t - 1.0), ramp(1.0 - f32::abs(2.0 * t - 1.0)), ramp(1.0 - 2.0 * t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_variance_matches_direct() {
+        let values = [3.0, 7.0, 1.0, 9.0, 4.0, 6.0];
+        let mut stats = PixelVariance::default();
+        for v in values {
+            stats.add(v);
+        }
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / (values.len() - 1) as f32;
+        assert!(f32::abs(stats.mean - mean) < 1e-5);
+        assert!(f32::abs(stats.variance() - variance) < 1e-4);
+    }
+
+    #[test]
+    fn flat_pixels_converge_and_noisy_ones_do_not() {
+        let mut flat = PixelVariance::default();
+        let mut noisy = PixelVariance::default();
+        for i in 0..8 {
+            flat.add(120.0);
+            noisy.add(if i % 2 == 0 { 0.0 } else { 255.0 });
+        }
+        assert!(flat.converged(0.05));
+        assert!(!noisy.converged(0.05));
+    }
+}
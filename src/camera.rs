@@ -0,0 +1,517 @@
+use std::f32::consts::{PI, FRAC_PI_2};
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::matrix::Matrix3;
+use crate::quaternion::Quaternion;
+use crate::sampling;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::filter::Filter;
+use crate::adaptive::AdaptiveSampling;
+use crate::integrator::IntegratorKind;
+
+// How pixels map to rays leaving the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // pinhole or thin lens, rays fan out from the eye according to fov_y
+    Perspective,
+    // parallel rays along the view direction. view_width is the horizontal extent of the image in
+    // scene units, the height follows from the aspect ratio
+    Orthographic { view_width : f32 },
+    // full sphere, longitude along x and latitude along y. the image should be twice as wide as tall
+    Equirectangular,
+    // circular fisheye inscribed in the shorter image side, fov is the full angle across the circle
+    Fisheye { fov : f32, mapping : FisheyeMapping },
+    // six 90 degree faces, see CubemapLayout
+    Cubemap { layout : CubemapLayout },
+    // omnidirectional stereo equirectangular. rays leave from a circle of radius |eye_offset|
+    // tangent to their horizontal direction, positive for the right eye and negative for the left
+    OmniStereo { eye_offset : f32 },
+}
+
+impl Projection {
+    // parses the names accepted on the command line
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic { view_width : 6.0 }),
+            "equirect" => Some(Projection::Equirectangular),
+            "fisheye" => Some(Projection::Fisheye { fov : PI, mapping : FisheyeMapping::Equisolid }),
+            "fisheye-equidistant" => Some(Projection::Fisheye { fov : PI, mapping : FisheyeMapping::Equidistant }),
+            "cubemap-strip" => Some(Projection::Cubemap { layout : CubemapLayout::Strip }),
+            "cubemap-cross" => Some(Projection::Cubemap { layout : CubemapLayout::Cross }),
+            _ => None,
+        }
+    }
+
+    // image size in pixels for a panorama laid out in square tiles of face pixels, the cube faces
+    // or a 4 by 2 grid for equirectangular images. None for projections that fit any size
+    pub fn panorama_size(&self, face : u16) -> Option<(u16, u16)> {
+        match self {
+            Projection::Equirectangular | Projection::OmniStereo { .. } => Some((4 * face, 2 * face)),
+            Projection::Cubemap { layout : CubemapLayout::Strip } => Some((6 * face, face)),
+            Projection::Cubemap { layout : CubemapLayout::Cross } => Some((4 * face, 3 * face)),
+            _ => None,
+        }
+    }
+}
+
+// how the angle from the view axis maps to the radius on a fisheye image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FisheyeMapping {
+    // radius proportional to the angle
+    Equidistant,
+    // radius proportional to 2 sin(angle / 2), equal solid angles cover equal image areas
+    Equisolid,
+}
+
+// face order and orientation follow the OpenGL cubemap convention
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubemapLayout {
+    // +x -x +y -y +z -z side by side, the image is six faces wide and one tall
+    Strip,
+    // horizontal cross, four faces wide and three tall:
+    //       +y
+    //    -x +z +x -z
+    //       -y
+    Cross,
+}
+
+// direction for the point (s, t) in [-1, 1]^2 on a cube face, s to the right and t downwards
+fn cube_face_dir(face : usize, s : f32, t : f32) -> V3 {
+    match face {
+        0 => V3::new(1.0, -t, -s),
+        1 => V3::new(-1.0, -t, s),
+        2 => V3::new(s, 1.0, t),
+        3 => V3::new(s, -1.0, -t),
+        4 => V3::new(s, -t, 1.0),
+        _ => V3::new(-s, -t, -1.0),
+    }
+}
+
+// Where one camera ray samples the pixel, the aperture and the shutter interval. Every component
+// is a uniform sample in [0, 1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraSample {
+    pub pixel : V2, // (0.5, 0.5) is the pixel centre
+    pub lens : V2,
+    pub time : f32,
+}
+
+impl CameraSample {
+    pub fn new(pixel : V2, lens : V2, time : f32) -> Self {
+        CameraSample {
+            pixel,
+            lens,
+            time,
+        }
+    }
+
+    // the camera always takes the first five dimensions of a pixel sample
+    pub fn from_sampler(sampler : &mut dyn Sampler) -> Self {
+        let pixel = sampler.get_2d();
+        let lens = sampler.get_2d();
+        Self::new(pixel, lens, sampler.get_1d())
+    }
+}
+
+#[derive(Clone)]
+pub struct Camera {
+    pub location : V3,
+    pub orientation : Quaternion, // rotates camera space (x right, y up, z forward) into world space
+    pub projection : Projection,
+    pub fov_y : f32, // vertical field of view in radians, the horizontal one follows from the aspect ratio
+    pub lens_shift : V2, // off axis shift of the perspective image, in units of the image plane at distance 1
+    pub aperture_radius : f32, // thin lens radius in scene units, 0 for a pinhole camera
+    pub focus_distance : f32, // distance along the view axis to the plane that is in focus
+    pub aperture_blades : u8, // 0 for a round aperture, otherwise the sides of the polygonal bokeh
+    pub blade_rotation : f32,
+    pub shutter_open : f32, // moving geometry is blurred over [shutter_open, shutter_close]
+    pub shutter_close : f32,
+    pub size_x : u16,
+    pub size_y : u16,
+    pub rays_per_pixel : u16,
+    pub sampler : SamplerKind, // how the rays of a pixel spread over the pixel, lens, shutter and bounces
+    pub seed : u32, // renders with equal settings and seed are bit identical, change it for a new noise pattern
+    pub filter : Filter, // how samples are weighted into the pixels around them
+    pub adaptive : Option<AdaptiveSampling>, // keep sampling noisy pixels past rays_per_pixel
+    pub integrator : IntegratorKind, // the algorithm that turns camera rays into colors
+    pub bounce_depth : u16, // hard limit on bounces, paths that get this far end regardless of roulette
+    pub roulette_depth : u16, // bounces before russian roulette may end a path early
+    pub max_steps : u16, 
+    pub step_len : f32, // discretization of the ray allows for the modelling of non linear space
+}
+
+impl Camera {
+    // points the camera at target. up only needs to be roughly perpendicular to the view direction
+    pub fn look_at(&mut self, eye : V3, target : V3, up : V3) {
+        let forward = (target - eye).normalized();
+        let mut right = up.cross(forward);
+        if right.magnitude() < 1e-6 {
+            // looking straight along up, any right vector will do
+            right = forward.orthonormal_basis().0;
+        }
+        let right = right.normalized();
+        let true_up = forward.cross(right);
+        self.location = eye;
+        self.orientation = Quaternion::from_matrix3(&Matrix3::new(right, true_up, forward));
+    }
+
+    // yaw around world y, then pitch around the camera x axis, then roll around the view direction.
+    // positive pitch looks down
+    pub fn set_euler(&mut self, yaw : f32, pitch : f32, roll : f32) {
+        let y = Quaternion::from_axis_angle(V3::new(0.0, 1.0, 0.0), yaw);
+        let x = Quaternion::from_axis_angle(V3::new(1.0, 0.0, 0.0), pitch);
+        let z = Quaternion::from_axis_angle(V3::new(0.0, 0.0, 1.0), roll);
+        self.orientation = (y * x * z).normalized();
+    }
+
+    // inverse of set_euler, returns (yaw, pitch, roll)
+    pub fn euler(&self) -> (f32, f32, f32) {
+        let forward = self.forward();
+        let right = self.right();
+        let up = self.up();
+        let pitch = f32::asin(f32::clamp(-forward.y, -1.0, 1.0));
+        let yaw = f32::atan2(forward.x, forward.z);
+        let roll = f32::atan2(right.y, up.y);
+        (yaw, pitch, roll)
+    }
+
+    // roll around the current view direction
+    pub fn roll(&mut self, theta : f32) {
+        let z = Quaternion::from_axis_angle(V3::new(0.0, 0.0, 1.0), theta);
+        self.orientation = (self.orientation * z).normalized();
+    }
+
+    pub fn forward(&self) -> V3 {
+        self.orientation.rotate(V3::new(0.0, 0.0, 1.0))
+    }
+
+    pub fn right(&self) -> V3 {
+        self.orientation.rotate(V3::new(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> V3 {
+        self.orientation.rotate(V3::new(0.0, 1.0, 0.0))
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.size_x as f32 / self.size_y as f32
+    }
+
+    // sets the aperture from an f-number, given the sensor height in scene units (0.024 for a full
+    // frame sensor with the scene in metres). the focal length follows from the field of view
+    pub fn set_f_stop(&mut self, f_stop : f32, sensor_height : f32) {
+        let focal_length = 0.5 * sensor_height / f32::tan(0.5 * self.fov_y);
+        self.aperture_radius = 0.5 * focal_length / f_stop;
+    }
+
+    // point on the lens in camera space for a uniform sample in [0, 1)^2
+    fn sample_lens(&self, lens_sample : V2) -> V3 {
+        if self.aperture_radius <= 0.0 {
+            return V3::zero();
+        }
+        let p = if self.aperture_blades == 0 {
+            sampling::concentric_disk(lens_sample)
+        }
+        else {
+            sampling::regular_polygon(lens_sample, self.aperture_blades, self.blade_rotation)
+        };
+        V3::new(p.x, p.y, 0.0) * self.aperture_radius
+    }
+
+    // position of a point inside pixel (x_idx, y_idx) on the image, from (-1, -1) at the bottom left
+    // to (1, 1) at the top right
+    fn pixel_ndc(&self, x_idx : u16, y_idx : u16, pixel_sample : V2) -> V2 {
+        V2::new(
+            2.0 * (x_idx as f32 + pixel_sample.x) / self.size_x as f32 - 1.0,
+            1.0 - 2.0 * (y_idx as f32 + pixel_sample.y) / self.size_y as f32,
+        )
+    }
+
+    // builds a world space ray from an origin and direction in camera space
+    fn camera_ray(&self, origin : V3, dir : V3) -> Ray {
+        Ray {
+            origin : self.location + self.orientation.rotate(origin),
+            dir : self.orientation.rotate(dir).normalized(),
+            steps_remaining : self.max_steps,
+            time : self.shutter_open,
+        }
+    }
+
+    // direction in camera space for a point on a panoramic image, None where the image is unused
+    fn panorama_dir(&self, ndc : V2) -> Option<V3> {
+        match self.projection {
+            Projection::Equirectangular | Projection::OmniStereo { .. } => {
+                let longitude = ndc.x * PI;
+                let latitude = ndc.y * FRAC_PI_2;
+                Some(V3::new(
+                    f32::cos(latitude) * f32::sin(longitude),
+                    f32::sin(latitude),
+                    f32::cos(latitude) * f32::cos(longitude),
+                ))
+            }
+            Projection::Fisheye { fov, mapping } => {
+                // scale so the circle touches the shorter side of the image
+                let aspect = self.aspect_ratio();
+                let p = if aspect >= 1.0 { V2::new(ndc.x * aspect, ndc.y) } else { V2::new(ndc.x, ndc.y / aspect) };
+                let r = p.magnitude();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * 0.5 * fov,
+                    FisheyeMapping::Equisolid => 2.0 * f32::asin(f32::min(r * f32::sin(0.25 * fov), 1.0)),
+                };
+                let phi = f32::atan2(p.y, p.x);
+                Some(V3::new(
+                    f32::sin(theta) * f32::cos(phi),
+                    f32::sin(theta) * f32::sin(phi),
+                    f32::cos(theta),
+                ))
+            }
+            Projection::Cubemap { layout } => {
+                // image coordinates in units of faces, y downwards
+                let (faces_x, faces_y) = match layout {
+                    CubemapLayout::Strip => (6.0, 1.0),
+                    CubemapLayout::Cross => (4.0, 3.0),
+                };
+                let u = 0.5 * (ndc.x + 1.0) * faces_x;
+                let v = 0.5 * (1.0 - ndc.y) * faces_y;
+                let (col, row) = (f32::min(u.floor(), faces_x - 1.0), f32::min(v.floor(), faces_y - 1.0));
+                let face = match layout {
+                    CubemapLayout::Strip => col as usize,
+                    CubemapLayout::Cross => match (col as usize, row as usize) {
+                        (1, 0) => 2,
+                        (0, 1) => 1,
+                        (1, 1) => 4,
+                        (2, 1) => 0,
+                        (3, 1) => 5,
+                        (1, 2) => 3,
+                        _ => return None,
+                    },
+                };
+                let s = 2.0 * (u - col) - 1.0;
+                let t = 2.0 * (v - row) - 1.0;
+                Some(cube_face_dir(face, s, t))
+            }
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+        }
+    }
+
+    fn projected_ray(&self, ndc : V2, lens_point : V3) -> Option<Ray> {
+        let ray = match self.projection {
+            Projection::Perspective => {
+                let half_height = f32::tan(0.5 * self.fov_y);
+                let half_width = half_height * self.aspect_ratio();
+                let dir = V3::new(ndc.x * half_width + self.lens_shift.x, ndc.y * half_height + self.lens_shift.y, 1.0);
+
+                // every ray through this pixel converges on the same point of the focal plane. a
+                // plane at or behind the lens would leave no direction, so it stays just in front
+                let focus_point = dir * f32::max(self.focus_distance, 1e-3);
+                self.camera_ray(lens_point, focus_point - lens_point)
+            }
+            Projection::Orthographic { view_width } => {
+                let half_width = 0.5 * view_width;
+                let half_height = half_width / self.aspect_ratio();
+                let film_point = V3::new(ndc.x * half_width, ndc.y * half_height, 0.0);
+                self.camera_ray(film_point, V3::new(0.0, 0.0, 1.0))
+            }
+            // panoramas are captured from a single point, the aperture is ignored
+            Projection::OmniStereo { eye_offset } => {
+                let longitude = ndc.x * PI;
+                let eye = V3::new(f32::cos(longitude), 0.0, -f32::sin(longitude)) * eye_offset;
+                self.camera_ray(eye, self.panorama_dir(ndc)?)
+            }
+            _ => self.camera_ray(V3::zero(), self.panorama_dir(ndc)?),
+        };
+        Some(ray)
+    }
+
+    // ray through a point inside pixel (x_idx, y_idx), pixel (0, 0) being the top left. the lens
+    // sample picks the point on the aperture the ray leaves from, which is ignored for pinholes.
+    // None for pixels outside the image area of fisheye and cubemap cross projections
+    pub fn pixel_ray(&self, x_idx : u16, y_idx : u16, sample : CameraSample) -> Option<Ray> {
+        let ndc = self.pixel_ndc(x_idx, y_idx, sample.pixel);
+        let mut ray = self.projected_ray(ndc, self.sample_lens(sample.lens))?;
+        ray.time = self.shutter_open + sample.time * (self.shutter_close - self.shutter_open);
+        Some(ray)
+    }
+
+    // ray through the centre of a pixel and the centre of the lens at shutter open, i.e. without any blur
+    pub fn centre_ray(&self, x_idx : u16, y_idx : u16) -> Option<Ray> {
+        let ndc = self.pixel_ndc(x_idx, y_idx, V2::new(0.5, 0.5));
+        self.projected_ray(ndc, V3::zero())
+    }
+
+    // starts sample sample_index of pixel (x_idx, y_idx) on the sampler and returns its camera ray,
+    // which needs to be simulated by the caller with the same sampler, along with where on the
+    // film it was taken in pixels. rays are spread across the pixel footprint to antialias edges,
+    // except with a single, non adaptive ray per pixel which goes through the centre. None for
+    // pixels no ray passes through, such as the corners of a fisheye image
+    pub fn generate_ray(&self, x_idx : u16, y_idx : u16, sample_index : u32, sampler : &mut dyn Sampler) -> Option<(Ray, V2)> {
+        sampler.start_pixel_sample(x_idx, y_idx, sample_index);
+        let mut sample = CameraSample::from_sampler(sampler);
+        if self.rays_per_pixel == 1 && self.adaptive.is_none() {
+            sample.pixel = V2::new(0.5, 0.5);
+        }
+        let film_position = V2::new(x_idx as f32, y_idx as f32) + sample.pixel;
+        Some((self.pixel_ray(x_idx, y_idx, sample)?, film_position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_camera() -> Camera {
+        Camera {
+            location : V3::zero(),
+            orientation : Quaternion::identity(),
+            projection : Projection::Perspective,
+            fov_y : 1.0,
+            lens_shift : V2::zero(),
+            aperture_radius : 0.0,
+            focus_distance : 1.0,
+            aperture_blades : 0,
+            blade_rotation : 0.0,
+            shutter_open : 0.0,
+            shutter_close : 0.0,
+            size_x : 8,
+            size_y : 4,
+            rays_per_pixel : 1,
+            sampler : SamplerKind::Independent,
+            seed : 0,
+            filter : Filter::pixel_box(),
+            adaptive : None,
+            integrator : IntegratorKind::Path,
+            bounce_depth : 1,
+            roulette_depth : 1,
+            max_steps : 1,
+            step_len : 1000.0,
+        }
+    }
+
+    #[test]
+    fn euler_roundtrips() {
+        let mut camera = test_camera();
+        camera.set_euler(0.7, -0.4, 0.25);
+        let (yaw, pitch, roll) = camera.euler();
+        assert!(f32::abs(yaw - 0.7) < 1e-4 && f32::abs(pitch + 0.4) < 1e-4 && f32::abs(roll - 0.25) < 1e-4);
+    }
+
+    #[test]
+    fn look_at_faces_target() {
+        let mut camera = test_camera();
+        let eye = V3::new(1.0, 2.0, 3.0);
+        let target = V3::new(-2.0, 0.0, 5.0);
+        camera.look_at(eye, target, V3::new(0.0, 1.0, 0.0));
+        assert!((camera.forward() - (target - eye).normalized()).magnitude() < 1e-5);
+        // no roll, so the right vector stays horizontal
+        assert!(f32::abs(camera.right().y) < 1e-5);
+        assert!(camera.up().y > 0.0);
+    }
+
+    #[test]
+    fn pixel_rays_span_field_of_view() {
+        let camera = test_camera();
+        let centre = camera.pixel_ray(4, 2, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap();
+        assert!((centre.dir - V3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        assert!((centre.origin - camera.location).magnitude() < 1e-5);
+
+        // top left corner is fov_y / 2 above the axis and aspect times wider to the side
+        let corner = camera.pixel_ray(0, 0, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+        let half_height = f32::tan(0.5 * camera.fov_y);
+        assert!(f32::abs(corner.y / corner.z - half_height) < 1e-5);
+        assert!(f32::abs(corner.x / corner.z + 2.0 * half_height) < 1e-5);
+    }
+
+    #[test]
+    fn lens_rays_converge_on_focal_plane() {
+        let mut camera = test_camera();
+        camera.aperture_radius = 0.5;
+        camera.focus_distance = 3.0;
+        camera.aperture_blades = 6;
+        let pixel_sample = V2::new(0.3, 0.8);
+        let pinhole = camera.pixel_ray(1, 3, CameraSample::new(pixel_sample, V2::new(0.5, 0.5), 0.0)).unwrap();
+        let focus = pinhole.origin + pinhole.dir * (3.0 / pinhole.dir.z);
+        for lens_sample in [V2::new(0.1, 0.9), V2::new(0.7, 0.2), V2::new(0.99, 0.5)] {
+            let ray = camera.pixel_ray(1, 3, CameraSample::new(pixel_sample, lens_sample, 0.0)).unwrap();
+            assert!(ray.origin.magnitude() <= 0.5 + 1e-5);
+            let hit = ray.origin + ray.dir * ((3.0 - ray.origin.z) / ray.dir.z);
+            assert!((hit - focus).magnitude() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn focusing_on_the_lens_still_gives_rays() {
+        let mut camera = test_camera();
+        for (aperture_radius, focus_distance) in [(0.0, 0.0), (0.5, 0.0), (0.5, -1.0)] {
+            camera.aperture_radius = aperture_radius;
+            camera.focus_distance = focus_distance;
+            let ray = camera.pixel_ray(2, 1, CameraSample::new(V2::new(0.3, 0.8), V2::new(0.7, 0.2), 0.0)).unwrap();
+            assert!(ray.dir.x.is_finite() && ray.dir.y.is_finite() && ray.dir.z > 0.0);
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut camera = test_camera();
+        camera.projection = Projection::Orthographic { view_width : 4.0 };
+        camera.set_euler(0.3, 0.2, 0.0);
+        let forward = camera.forward();
+        let top_left = camera.pixel_ray(0, 0, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap();
+        let bottom_right = camera.pixel_ray(8, 4, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap();
+        assert!((top_left.dir - forward).magnitude() < 1e-5);
+        assert!((bottom_right.dir - forward).magnitude() < 1e-5);
+        // 8x4 pixels at a width of 4 units makes the image 2 units tall
+        let span = bottom_right.origin - top_left.origin;
+        assert!(f32::abs(span.dot(camera.right()) - 4.0) < 1e-4);
+        assert!(f32::abs(span.dot(camera.up()) + 2.0) < 1e-4);
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let mut camera = test_camera();
+        camera.projection = Projection::Equirectangular;
+        let centre = camera.pixel_ray(4, 2, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+        assert!((centre - V3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        let left_edge = camera.pixel_ray(0, 2, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+        assert!((left_edge - V3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+        let top = camera.pixel_ray(4, 0, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+        assert!((top - V3::new(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn fisheye_maps_edge_to_half_fov() {
+        let mut camera = test_camera();
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            camera.projection = Projection::Fisheye { fov : PI, mapping };
+            // the circle touches the top edge, which is 90 degrees off axis for a 180 degree lens
+            let top = camera.pixel_ray(4, 0, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+            assert!(f32::abs(top.z) < 1e-4 && top.y > 0.99);
+            // image corners are outside the circle
+            assert!(camera.pixel_ray(0, 0, CameraSample::new(V2::new(0.5, 0.5), V2::zero(), 0.0)).is_none());
+        }
+    }
+
+    #[test]
+    fn cubemap_faces_look_along_axes() {
+        let mut camera = test_camera();
+        camera.size_x = 12;
+        camera.size_y = 2;
+        camera.projection = Projection::Cubemap { layout : CubemapLayout::Strip };
+        let axes = [V3::new(1.0, 0.0, 0.0), V3::new(-1.0, 0.0, 0.0), V3::new(0.0, 1.0, 0.0),
+                    V3::new(0.0, -1.0, 0.0), V3::new(0.0, 0.0, 1.0), V3::new(0.0, 0.0, -1.0)];
+        for (face, axis) in axes.iter().enumerate() {
+            let dir = camera.pixel_ray(2 * face as u16 + 1, 1, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+            assert!((dir - *axis).magnitude() < 1e-5);
+        }
+
+        camera.size_x = 8;
+        camera.size_y = 6;
+        camera.projection = Projection::Cubemap { layout : CubemapLayout::Cross };
+        let front = camera.pixel_ray(3, 3, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap().dir;
+        assert!((front - V3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        assert!(camera.pixel_ray(0, 0, CameraSample::new(V2::new(0.5, 0.5), V2::zero(), 0.0)).is_none());
+    }
+}
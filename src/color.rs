@@ -1,4 +1,6 @@
 use std::ops;
+use crate::vector::V3;
+#[allow(dead_code)]
 pub struct Col4 {
     pub r : u8,
     pub g : u8,
@@ -55,9 +57,9 @@ impl ops::Mul<f32> for Col3 {
     
     fn mul(self, rh : f32) -> Col3 {
         Col3::new(
-            ((self.r as f32 * rh)) as u8,
-            ((self.b as f32 * rh)) as u8,
-            ((self.g as f32 * rh)) as u8,
+            (self.r as f32 * rh) as u8,
+            (self.b as f32 * rh) as u8,
+            (self.g as f32 * rh) as u8,
         )
     }
 }
@@ -73,8 +75,7 @@ impl ops::Mul<Col3> for f32 {
 impl Col3 {
     // returns a color whose constituent colors are 0xff - itself
     pub fn inverse(&self) -> Col3 {
-        let mut raw_invert = (Col3::white() - *self);
-        raw_invert
+        Col3::white() - *self
     }
     // generate a color as if it was reflected by a surface that has color col. 
     // equivalent to having the inverse absorption spectrum of said color
@@ -106,5 +107,10 @@ impl Col3 {
         }
     }
 
+    // channels as floats, still on the 0-255 scale
+    pub fn to_v3(self) -> V3 {
+        V3::new(self.r as f32, self.g as f32, self.b as f32)
+    }
+
     
 }
@@ -0,0 +1,125 @@
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::shape::RayTraceShape;
+use crate::material::Material;
+use crate::medium::component;
+
+// An axis aligned box between the corners min and max, e.g. the boundary of a voxel grid volume.
+#[derive(Clone, Copy)]
+pub struct Cuboid {
+    pub min : V3,
+    pub max : V3,
+    pub material : Material,
+}
+
+// distances along origin + dir * t at which a ray enters and leaves the box between min and max,
+// with near > far when it misses
+pub fn slab_interval(origin : V3, dir : V3, min : V3, max : V3) -> (f32, f32) {
+    let (mut near, mut far) = (f32::NEG_INFINITY, f32::INFINITY);
+    for axis in 0..3 {
+        let o = component(origin, axis);
+        let d = component(dir, axis);
+        let (low, high) = (component(min, axis), component(max, axis));
+        if d == 0.0 {
+            if o < low || o > high {
+                return (f32::INFINITY, f32::NEG_INFINITY);
+            }
+            continue;
+        }
+        let (a, b) = ((low - o) / d, (high - o) / d);
+        near = f32::max(near, f32::min(a, b));
+        far = f32::min(far, f32::max(a, b));
+    }
+    (near, far)
+}
+
+impl Cuboid {
+    fn face_areas(&self) -> V3 {
+        let size = self.max - self.min;
+        V3::new(size.y * size.z, size.x * size.z, size.x * size.y)
+    }
+}
+
+impl RayTraceShape for Cuboid {
+    fn intersect(&self, ray : Ray) -> Option<f32> {
+        let (near, far) = slab_interval(ray.origin, ray.dir, self.min, self.max);
+        if near > far {
+            return None;
+        }
+        if near > 0.001 {
+            Some(near)
+        }
+        else if far > 0.001 {
+            Some(far)
+        }
+        else {
+            None
+        }
+    }
+
+
+    // normal of the face the point is closest to
+    fn normal_at(&self, point : V3, _time : f32) -> V3 {
+        let mut best = (f32::INFINITY, V3::zero());
+        for axis in 0..3 {
+            let p = component(point, axis);
+            let unit = V3::new((axis == 0) as u8 as f32, (axis == 1) as u8 as f32, (axis == 2) as u8 as f32);
+            for (distance, normal) in [(p - component(self.min, axis), -unit), (component(self.max, axis) - p, unit)] {
+                if f32::abs(distance) < best.0 {
+                    best = (f32::abs(distance), normal);
+                }
+            }
+        }
+        best.1
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        let faces = self.face_areas();
+        2.0 * (faces.x + faces.y + faces.z)
+    }
+
+    // picks one of the six faces by area with u.x, then reuses what is left of u.x on it
+    fn sample_point(&self, u : V2, _time : f32) -> (V3, V3) {
+        let faces = self.face_areas();
+        let mut target = u.x * (faces.x + faces.y + faces.z);
+        let mut axis = 0;
+        while axis < 2 && target >= component(faces, axis) {
+            target -= component(faces, axis);
+            axis += 1;
+        }
+        let share = f32::clamp(target / component(faces, axis), 0.0, 1.0);
+        // the front half of the share picks the low face, the back half the high one
+        let (high, s) = if share < 0.5 { (false, share * 2.0) } else { (true, share * 2.0 - 1.0) };
+        let size = self.max - self.min;
+        let offsets = match axis {
+            0 => V3::new(if high { size.x } else { 0.0 }, s * size.y, u.y * size.z),
+            1 => V3::new(s * size.x, if high { size.y } else { 0.0 }, u.y * size.z),
+            _ => V3::new(s * size.x, u.y * size.y, if high { size.z } else { 0.0 }),
+        };
+        let point = self.min + offsets;
+        (point, self.normal_at(point, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Col3;
+
+    #[test]
+    fn rays_enter_and_leave_the_box() {
+        let cuboid = Cuboid { min : V3::new(-1.0, -1.0, 2.0), max : V3::new(1.0, 1.0, 4.0), material : Material::new(0.0, Col3::black()) };
+        let ray = Ray::new(V3::zero(), V3::new(0.0, 0.0, 1.0), 1);
+        assert_eq!(cuboid.intersect(ray), Some(2.0));
+        assert_eq!(cuboid.normal_at(V3::new(0.0, 0.0, 2.0), 0.0), V3::new(0.0, 0.0, -1.0));
+        let inside = Ray::new(V3::new(0.0, 0.0, 3.0), V3::new(1.0, 0.0, 0.0), 1);
+        assert_eq!(cuboid.intersect(inside), Some(1.0));
+        let miss = Ray::new(V3::zero(), V3::new(0.0, 1.0, 0.0), 1);
+        assert_eq!(cuboid.intersect(miss), None);
+        assert_eq!(cuboid.area(), 24.0);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::shape::RayTraceShape;
+use crate::material::Material;
+use crate::light_tree::LightBounds;
+use crate::sampling;
+
+// A flat disk around origin, facing along normal. An emissive disk only shines from that side.
+#[derive(Clone, Copy)]
+pub struct Disk {
+    pub origin : V3,
+    pub normal : V3,
+    pub radius : f32,
+    pub material : Material,
+}
+
+impl RayTraceShape for Disk {
+    fn intersect(&self, ray : Ray) -> Option<f32> {
+        let denom = self.normal.dot(ray.dir);
+        if f32::abs(denom) < 1e-8 {
+            return None;
+        }
+        let t = -self.normal.dot(ray.origin - self.origin) / denom;
+        if t <= 0.001 {
+            return None;
+        }
+        let offset = ray.origin + ray.dir * t - self.origin;
+        if offset.magnitude_squared() <= self.radius * self.radius {
+            Some(t)
+        }
+        else {
+            None
+        }
+    }
+
+
+    fn normal_at(&self, _point : V3, _time : f32) -> V3 {
+        self.normal.normalized()
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        std::f32::consts::PI * self.radius * self.radius
+    }
+
+    fn sample_point(&self, u : V2, _time : f32) -> (V3, V3) {
+        let normal = self.normal.normalized();
+        let d = sampling::concentric_disk(u) * self.radius;
+        (self.origin + sampling::to_world(V3::new(d.x, d.y, 0.0), normal), normal)
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        let extent = V3::new(self.radius, self.radius, self.radius);
+        LightBounds::surface(self.origin - extent, self.origin + extent, self.normal, 1.0, self.material.emission, self.area())
+    }
+}
@@ -0,0 +1,380 @@
+use std::f32::consts::{PI, FRAC_PI_2};
+use std::io;
+use std::path::Path;
+use crate::vector::{V2, V3};
+use crate::sampling::Distribution2D;
+use crate::adaptive::luminance;
+
+// Equirectangular HDR image surrounding the scene. It is what rays that miss all geometry see,
+// and it lights the scene like a huge emitter. The layout matches the camera's equirectangular
+// projection: longitude runs along x with +z in the middle, latitude along y with +y at the top.
+pub struct EnvironmentMap {
+    pub width : usize,
+    pub height : usize,
+    pub pixels : Vec<V3>, // linear radiance row by row from the top, 1.0 being white
+    pub rotation : f32, // radians around the world y axis
+    pub intensity : f32,
+    tint : V3, // multiplies every pixel, see set_tint
+    // tinted luminance weighted by the solid angle each pixel covers
+    distribution : Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(width : usize, height : usize, pixels : Vec<V3>) -> Self {
+        assert!(width > 0 && height > 0, "environment map needs at least one pixel");
+        assert_eq!(pixels.len(), width * height, "environment map has {} pixels, expected {}x{}", pixels.len(), width, height);
+        let tint = V3::new(1.0, 1.0, 1.0);
+        EnvironmentMap {
+            width,
+            height,
+            distribution : build_distribution(width, height, &pixels, tint),
+            pixels,
+            rotation : 0.0,
+            intensity : 1.0,
+            tint,
+        }
+    }
+
+    // colors the whole map, e.g. with spectrum::blackbody_normalized for a warm or cold cast. the
+    // sampling distribution is rebuilt, so a tint that darkens some pixels more than others is
+    // followed by importance sampling
+    pub fn set_tint(&mut self, tint : V3) {
+        self.tint = tint;
+        self.distribution = build_distribution(self.width, self.height, &self.pixels, tint);
+    }
+
+    // builds the map by evaluating radiance for the direction through each pixel centre
+    pub fn from_fn(width : usize, height : usize, radiance : impl Fn(V3) -> V3) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(radiance(uv_to_dir(V2::new((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32))));
+            }
+        }
+        Self::new(width, height, pixels)
+    }
+
+    // reads a Radiance .hdr or an OpenEXR file, chosen by extension
+    pub fn load(path : impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        match extension.as_str() {
+            "hdr" | "pic" => {
+                let (width, height, pixels) = parse_hdr(&std::fs::read(path)?)?;
+                Ok(Self::new(width, height, pixels))
+            }
+            "exr" => {
+                let (width, height, pixels) = load_exr(path)?;
+                Ok(Self::new(width, height, pixels))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported environment map {}", path.display()))),
+        }
+    }
+
+    // radiance arriving from the world space direction dir, on the 0-255 scale
+    pub fn radiance(&self, dir : V3) -> V3 {
+        let uv = dir_to_uv(self.to_local(dir.normalized()));
+        let x = usize::min((uv.x * self.width as f32) as usize, self.width - 1);
+        let y = usize::min((uv.y * self.height as f32) as usize, self.height - 1);
+        self.pixels[y * self.width + x].mul_elem(self.tint) * (255.0 * self.intensity)
+    }
+
+    // luminance of the irradiance on a surface, averaged over the directions it could face,
+    // for weighing the map against other lights
+    pub fn irradiance(&self) -> f32 {
+        let mut total = 0.0;
+        for y in 0..self.height {
+            let sin_theta = f32::sin(PI * (y as f32 + 0.5) / self.height as f32);
+            for x in 0..self.width {
+                total += luminance(self.pixels[y * self.width + x].mul_elem(self.tint)) * sin_theta;
+            }
+        }
+        // the pixels cover 2 pi^2 sin theta / (width height) steradians each, and a surface
+        // sees half the sphere weighted by cosine, which averages to pi times the mean radiance
+        let mean_radiance = total * 2.0 * PI * PI / (self.width * self.height) as f32 / (4.0 * PI);
+        PI * mean_radiance * 255.0 * self.intensity
+    }
+
+    // direction towards a bright part of the map, with its solid angle density
+    pub fn sample(&self, u : V2) -> Option<(V3, f32)> {
+        let (uv, pdf_uv) = self.distribution.sample(u);
+        let sin_theta = f32::sin(PI * uv.y);
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // the map spans 2 pi by pi radians
+        Some((self.to_world(uv_to_dir(uv)), pdf_uv / (2.0 * PI * PI * sin_theta)))
+    }
+
+    pub fn pdf(&self, dir : V3) -> f32 {
+        let uv = dir_to_uv(self.to_local(dir.normalized()));
+        let sin_theta = f32::sin(PI * uv.y);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(uv) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn to_local(&self, dir : V3) -> V3 {
+        rotate_y(dir, -self.rotation)
+    }
+
+    fn to_world(&self, dir : V3) -> V3 {
+        rotate_y(dir, self.rotation)
+    }
+}
+
+fn rotate_y(v : V3, theta : f32) -> V3 {
+    let (sin, cos) = f32::sin_cos(theta);
+    V3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
+
+// image coordinates in [0, 1)^2, (0, 0) being the top left
+fn dir_to_uv(dir : V3) -> V2 {
+    let longitude = f32::atan2(dir.x, dir.z);
+    let latitude = f32::asin(f32::clamp(dir.y, -1.0, 1.0));
+    V2::new(0.5 * (longitude / PI + 1.0), 0.5 * (1.0 - latitude / FRAC_PI_2))
+}
+
+fn uv_to_dir(uv : V2) -> V3 {
+    let longitude = (2.0 * uv.x - 1.0) * PI;
+    let latitude = (1.0 - 2.0 * uv.y) * FRAC_PI_2;
+    V3::new(
+        f32::cos(latitude) * f32::sin(longitude),
+        f32::sin(latitude),
+        f32::cos(latitude) * f32::cos(longitude),
+    )
+}
+
+fn invalid(message : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// luminance of every tinted pixel weighted by its solid angle
+fn build_distribution(width : usize, height : usize, pixels : &[V3], tint : V3) -> Distribution2D {
+    let mut weights = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        // rows near the poles are squeezed onto a small part of the sphere
+        let sin_theta = f32::sin(PI * (y as f32 + 0.5) / height as f32);
+        for x in 0..width {
+            // infinite or nan pixels, which exr files can hold, would swamp every other weight
+            let weight = luminance(pixels[y * width + x].mul_elem(tint)) * sin_theta;
+            weights.push(if weight.is_finite() { weight } else { 0.0 });
+        }
+    }
+    Distribution2D::new(&weights, width, height)
+}
+
+// Radiance RGBE files: a text header, a resolution line and then scanlines that are either flat
+// or run length encoded per channel. Only the common -Y h +X w orientation is supported.
+pub fn parse_hdr(bytes : &[u8]) -> io::Result<(usize, usize, Vec<V3>)> {
+    let mut pos = 0;
+    let next_line = |pos : &mut usize| -> io::Result<String> {
+        let start = *pos;
+        while *pos < bytes.len() && bytes[*pos] != b'\n' {
+            *pos += 1;
+        }
+        if *pos >= bytes.len() {
+            return Err(invalid("hdr header ends early"));
+        }
+        *pos += 1;
+        Ok(String::from_utf8_lossy(&bytes[start..*pos - 1]).trim().to_string())
+    };
+
+    let magic = next_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid("not a radiance hdr file"));
+    }
+    loop {
+        let line = next_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("only rgbe hdr files are supported"));
+            }
+        }
+    }
+    let resolution = next_line(&mut pos)?;
+    let parts : Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match parts.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| invalid("bad hdr height"))?,
+            w.parse::<usize>().map_err(|_| invalid("bad hdr width"))?,
+        ),
+        _ => return Err(invalid("unsupported hdr orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("hdr image is empty"));
+    }
+    let pixel_count = width.checked_mul(height).ok_or_else(|| invalid("hdr image too large"))?;
+    // even run length encoded, 127 pixels of a scanline take at least 8 bytes, so a header asking
+    // for more pixels than the rest of the file can hold is broken
+    if pixel_count / 16 > bytes.len() - pos {
+        return Err(invalid("hdr data ends early"));
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        let rle = (8..=0x7fff).contains(&width)
+            && bytes.len() >= pos + 4
+            && bytes[pos] == 2 && bytes[pos + 1] == 2 && bytes[pos + 2] & 0x80 == 0;
+        if rle {
+            if ((bytes[pos + 2] as usize) << 8 | bytes[pos + 3] as usize) != width {
+                return Err(invalid("hdr scanline width mismatch"));
+            }
+            pos += 4;
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = *bytes.get(pos).ok_or_else(|| invalid("hdr data ends early"))? as usize;
+                    pos += 1;
+                    if count > 128 {
+                        let run = count - 128;
+                        let value = *bytes.get(pos).ok_or_else(|| invalid("hdr data ends early"))?;
+                        pos += 1;
+                        if x + run > width {
+                            return Err(invalid("hdr run overflows scanline"));
+                        }
+                        for pixel in &mut scanline[x..x + run] {
+                            pixel[channel] = value;
+                        }
+                        x += run;
+                    }
+                    else {
+                        if count == 0 || x + count > width || pos + count > bytes.len() {
+                            return Err(invalid("bad hdr literal run"));
+                        }
+                        for (pixel, &value) in scanline[x..x + count].iter_mut().zip(&bytes[pos..pos + count]) {
+                            pixel[channel] = value;
+                        }
+                        pos += count;
+                        x += count;
+                    }
+                }
+            }
+        }
+        else {
+            if pos + 4 * width > bytes.len() {
+                return Err(invalid("hdr data ends early"));
+            }
+            for (x, pixel) in scanline.iter_mut().enumerate() {
+                pixel.copy_from_slice(&bytes[pos + 4 * x..pos + 4 * x + 4]);
+            }
+            pos += 4 * width;
+        }
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_v3(rgbe)));
+    }
+    Ok((width, height, pixels))
+}
+
+// shared exponent in the fourth byte, biased by 128 and with the mantissas as 8 bit fractions
+fn rgbe_to_v3(rgbe : [u8; 4]) -> V3 {
+    if rgbe[3] == 0 {
+        return V3::zero();
+    }
+    let scale = f32::powi(2.0, rgbe[3] as i32 - 136);
+    V3::new(rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale)
+}
+
+fn load_exr(path : &Path) -> io::Result<(usize, usize, Vec<V3>)> {
+    use exr::prelude::*;
+    let image = read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| (resolution.width(), vec![V3::zero(); resolution.width() * resolution.height()]),
+        |(width, pixels), position, (r, g, b, _a) : (f32, f32, f32, f32)| {
+            pixels[position.y() * *width + position.x()] = V3::new(r, g, b);
+        },
+    ).map_err(|e| invalid(&e.to_string()))?;
+    let (width, pixels) = image.layer_data.channel_data.pixels;
+    if pixels.is_empty() {
+        return Err(invalid("exr image is empty"));
+    }
+    let height = pixels.len() / width;
+    Ok((width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flat_and_run_length_scanlines() {
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        // first scanline run length encoded: r and g runs of 8, b as literals, e as a run
+        file.extend([2, 2, 0, 8]);
+        file.extend([128 + 8, 128, 128 + 8, 64]);
+        file.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        file.extend([128 + 8, 129]);
+        // second scanline flat
+        for _ in 0..8 {
+            file.extend([128, 0, 0, 128]);
+        }
+        let (width, height, pixels) = parse_hdr(&file).unwrap();
+        assert_eq!((width, height), (8, 2));
+        assert_eq!(pixels[0], V3::new(1.0, 0.5, 0.0));
+        assert_eq!(pixels[2], V3::new(1.0, 0.5, 0.25));
+        assert_eq!(pixels[8], V3::new(0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn empty_hdr_images_are_errors() {
+        assert!(parse_hdr(b"#?RADIANCE\n\n-Y 0 +X 0\n").is_err());
+        assert!(parse_hdr(b"#?RADIANCE\n\n-Y 4 +X 0\n").is_err());
+    }
+
+    #[test]
+    fn oversized_hdr_headers_are_errors() {
+        let mut file = b"#?RADIANCE\n\n-Y 200000 +X 200000\n".to_vec();
+        file.extend([128, 0, 0, 128]);
+        assert!(parse_hdr(&file).is_err());
+    }
+
+    #[test]
+    fn directions_roundtrip_through_uv() {
+        for dir in [V3::new(0.0, 0.0, 1.0), V3::new(0.3, 0.8, -0.2).normalized(), V3::new(-1.0, -0.1, 0.1).normalized()] {
+            assert!((uv_to_dir(dir_to_uv(dir)) - dir).magnitude() < 1e-5);
+        }
+        assert!((dir_to_uv(V3::new(0.0, 0.0, 1.0)) - V2::new(0.5, 0.5)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn sampling_favours_bright_pixels_and_matches_pdf() {
+        let mut map = EnvironmentMap::from_fn(16, 8, |dir| if dir.x > 0.5 && dir.y > 0.0 { V3::new(8.0, 8.0, 8.0) } else { V3::new(0.1, 0.1, 0.1) });
+        map.rotation = 0.7;
+        let mut bright = 0;
+        for i in 0..64 {
+            let u = V2::new((i % 8) as f32 / 8.0 + 0.03, (i / 8) as f32 / 8.0 + 0.05);
+            let (dir, pdf) = map.sample(u).unwrap();
+            assert!(f32::abs(map.pdf(dir) - pdf) < 1e-3 * pdf);
+            if map.radiance(dir).x > 255.0 {
+                bright += 1;
+            }
+        }
+        assert!(bright > 48);
+    }
+
+    #[test]
+    fn sampling_follows_the_tint() {
+        let mut map = EnvironmentMap::from_fn(16, 8, |dir| if dir.x > 0.0 { V3::new(1.0, 0.0, 0.0) } else { V3::new(0.0, 0.0, 1.0) });
+        map.set_tint(V3::new(0.0, 1.0, 1.0));
+        for i in 0..16 {
+            let (dir, pdf) = map.sample(V2::new((i as f32 + 0.5) / 16.0, 0.5)).unwrap();
+            assert!(map.radiance(dir).z > 0.0);
+            assert!(f32::abs(map.pdf(dir) - pdf) < 1e-3 * pdf);
+        }
+        assert_eq!(map.pdf(V3::new(1.0, 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn non_finite_pixels_are_never_sampled() {
+        let map = EnvironmentMap::from_fn(16, 8, |dir| if dir.x > 0.9 { V3::new(f32::INFINITY, 0.0, f32::NAN) } else { V3::new(1.0, 1.0, 1.0) });
+        for i in 0..16 {
+            let (dir, pdf) = map.sample(V2::new((i as f32 + 0.5) / 16.0, 0.5)).unwrap();
+            assert!(pdf.is_finite() && pdf > 0.0);
+            assert!(map.radiance(dir).x.is_finite());
+        }
+    }
+}
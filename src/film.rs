@@ -0,0 +1,148 @@
+use std::ops::Range;
+use crate::vector::{V2, V3};
+use crate::color::Col3;
+use crate::filter::Filter;
+
+// Accumulates radiance samples into pixels. Every sample is splatted onto all pixels whose centre
+// lies within the filter radius, weighted by the filter, and each pixel ends up as the weighted
+// average of the samples around it.
+// A film can hold just a band of rows of the image, so parts of it can be traced separately and
+// merged afterwards.
+pub struct Film {
+    pub size_x : u16,
+    pub size_y : u16,
+    pub filter : Filter,
+    rows : Range<u16>, // the rows of the image this film holds
+    sums : Vec<V3>,
+    weights : Vec<f32>,
+}
+
+impl Film {
+    pub fn new(size_x : u16, size_y : u16, filter : Filter) -> Self {
+        Self::band(size_x, size_y, filter, 0..size_y)
+    }
+
+    // a film for the given rows of a size_x by size_y image. samples only reach the pixels in
+    // those rows
+    pub fn band(size_x : u16, size_y : u16, filter : Filter, rows : Range<u16>) -> Self {
+        let count = size_x as usize * rows.len();
+        Film {
+            size_x,
+            size_y,
+            filter,
+            rows,
+            sums : vec![V3::zero(); count],
+            weights : vec![0.0; count],
+        }
+    }
+
+    // the band of rows that samples taken in the given pixel rows can splat onto
+    pub fn rows_reached_from(&self, rows : Range<u16>) -> Range<u16> {
+        // samples lie within their pixel, at most half a pixel from its centre
+        let margin = f32::ceil(self.filter.radius + 0.5) as u16;
+        rows.start.saturating_sub(margin)..u16::min(rows.end.saturating_add(margin), self.size_y)
+    }
+
+    // adds the samples splatted onto a band of the same image to this film
+    pub fn merge(&mut self, band : &Film) {
+        assert!(band.size_x == self.size_x && band.rows.start >= self.rows.start && band.rows.end <= self.rows.end);
+        let offset = (band.rows.start - self.rows.start) as usize * self.size_x as usize;
+        for (i, (&sum, &weight)) in band.sums.iter().zip(band.weights.iter()).enumerate() {
+            self.sums[offset + i] += sum;
+            self.weights[offset + i] += weight;
+        }
+    }
+
+    // position is in pixels from the top left corner of the image, pixel (x, y) covering
+    // [x, x + 1) x [y, y + 1). value is the sample's rgb on the 0-255 scale
+    pub fn add_sample(&mut self, position : V2, value : V3) {
+        let radius = self.filter.radius;
+        // pixel centres sit at half integers
+        let x0 = f32::max(f32::ceil(position.x - 0.5 - radius), 0.0) as i32;
+        let y0 = f32::max(f32::ceil(position.y - 0.5 - radius), self.rows.start as f32) as i32;
+        let x1 = f32::min(f32::floor(position.x - 0.5 + radius), self.size_x as f32 - 1.0) as i32;
+        let y1 = f32::min(f32::floor(position.y - 0.5 + radius), self.rows.end as f32 - 1.0) as i32;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let offset = position - V2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weight = self.filter.evaluate(offset);
+                if weight == 0.0 {
+                    continue;
+                }
+                let idx = (y as usize - self.rows.start as usize) * self.size_x as usize + x as usize;
+                self.sums[idx] += value * weight;
+                self.weights[idx] += weight;
+            }
+        }
+    }
+
+    // weighted average of the samples splatted onto the pixel, black if none reached it. this is
+    // not clamped: filters with negative lobes can push it below black, and bright samples past
+    // white. to_image clamps
+    pub fn pixel(&self, x_idx : u16, y_idx : u16) -> V3 {
+        let idx = (y_idx - self.rows.start) as usize * self.size_x as usize + x_idx as usize;
+        let weight = self.weights[idx];
+        if weight <= 0.0 {
+            return V3::zero();
+        }
+        self.sums[idx] / weight
+    }
+
+    pub fn to_image(&self) -> Vec<Col3> {
+        let mut to_ret = Vec::with_capacity(self.sums.len());
+        for y in self.rows.clone() {
+            for x in 0..self.size_x {
+                let value = self.pixel(x, y);
+                // negative lobes and highlights leave the 0-255 range
+                let channel = |v : f32| f32::clamp(v, 0.0, 255.0) as u8;
+                to_ret.push(Col3::new(channel(value.x), channel(value.y), channel(value.z)));
+            }
+        }
+        to_ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn box_filter_averages_samples_within_the_pixel() {
+        let mut film = Film::new(2, 1, Filter::pixel_box());
+        film.add_sample(V2::new(0.2, 0.5), V3::new(100.0, 0.0, 0.0));
+        film.add_sample(V2::new(0.9, 0.1), V3::new(200.0, 0.0, 0.0));
+        film.add_sample(V2::new(1.5, 0.5), V3::new(0.0, 50.0, 0.0));
+        assert_eq!(film.pixel(0, 0), V3::new(150.0, 0.0, 0.0));
+        assert_eq!(film.pixel(1, 0), V3::new(0.0, 50.0, 0.0));
+    }
+
+    #[test]
+    fn wide_filters_splat_onto_neighbours() {
+        let mut film = Film::new(3, 1, Filter::new(FilterKind::Tent, 1.5));
+        film.add_sample(V2::new(1.5, 0.5), V3::new(90.0, 90.0, 90.0));
+        for x in 0..3 {
+            assert_eq!(film.pixel(x, 0), V3::new(90.0, 90.0, 90.0));
+        }
+    }
+
+    #[test]
+    fn merged_bands_match_one_film() {
+        let filter = Filter::new(FilterKind::Tent, 1.5);
+        let samples = [(V2::new(0.5, 0.2), 40.0), (V2::new(1.3, 1.9), 120.0), (V2::new(2.7, 3.5), 200.0), (V2::new(1.1, 3.9), 10.0)];
+        let mut whole = Film::new(3, 4, filter);
+        let mut merged = Film::new(3, 4, filter);
+        for (position, value) in samples {
+            let value = V3::new(value, value, value);
+            whole.add_sample(position, value);
+            let mut band = Film::band(3, 4, filter, whole.rows_reached_from(position.y as u16..position.y as u16 + 1));
+            band.add_sample(position, value);
+            merged.merge(&band);
+        }
+        for y in 0..4 {
+            for x in 0..3 {
+                assert!((whole.pixel(x, y) - merged.pixel(x, y)).magnitude() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::f32::consts::PI;
+use crate::vector::V2;
+
+// Shape of the pixel reconstruction filter. All of them are separable, the 2d weight is the
+// product of the 1d weights along x and y.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    // constant weight, a plain average of the samples inside the radius
+    Box,
+    // linear falloff towards the radius
+    Tent,
+    // gaussian with standard deviation sigma, shifted down so it reaches 0 at the radius
+    Gaussian { sigma : f32 },
+    // cubic from Mitchell and Netravali 1988, b = c = 1/3 is their recommended compromise between
+    // blurring and ringing
+    MitchellNetravali { b : f32, c : f32 },
+    // sinc windowed by a wider sinc that stretches over the whole radius. sharp, but rings slightly
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian { .. } => "gaussian",
+            FilterKind::MitchellNetravali { .. } => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+}
+
+// Weights samples by their offset from a pixel centre, in pixels. A radius of 0.5 only lets
+// samples contribute to the pixel they were taken in; larger radii spread them onto neighbours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind : FilterKind,
+    pub radius : f32,
+}
+
+impl Filter {
+    pub fn new(kind : FilterKind, radius : f32) -> Self {
+        Filter {
+            kind,
+            radius,
+        }
+    }
+
+    // the filter every sample averaging renderer starts out with, one pixel wide box
+    pub fn pixel_box() -> Self {
+        Self::new(FilterKind::Box, 0.5)
+    }
+
+    pub fn mitchell() -> Self {
+        Self::new(FilterKind::MitchellNetravali { b : 1.0 / 3.0, c : 1.0 / 3.0 }, 2.0)
+    }
+
+    pub fn evaluate(&self, offset : V2) -> f32 {
+        self.evaluate_1d(offset.x) * self.evaluate_1d(offset.y)
+    }
+
+    fn evaluate_1d(&self, x : f32) -> f32 {
+        let x = f32::abs(x);
+        if x > self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian { sigma } => {
+                let gaussian = |v : f32| f32::exp(-v * v / (2.0 * sigma * sigma));
+                f32::max(gaussian(x) - gaussian(self.radius), 0.0)
+            }
+            FilterKind::MitchellNetravali { b, c } => mitchell_1d(2.0 * x / self.radius, b, c),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+
+    // next filter in the order the viewer cycles through them, each at a radius that suits it
+    pub fn next(self) -> Self {
+        match self.kind {
+            FilterKind::Box => Self::new(FilterKind::Tent, 1.0),
+            FilterKind::Tent => Self::new(FilterKind::Gaussian { sigma : 0.5 }, 1.5),
+            FilterKind::Gaussian { .. } => Self::mitchell(),
+            FilterKind::MitchellNetravali { .. } => Self::new(FilterKind::Lanczos, 3.0),
+            FilterKind::Lanczos => Self::pixel_box(),
+        }
+    }
+}
+
+// x in [0, 2]
+fn mitchell_1d(x : f32, b : f32, c : f32) -> f32 {
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    }
+    else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)) / 6.0
+    }
+}
+
+fn sinc(x : f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    f32::sin(PI * x) / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_peak_at_the_centre_and_vanish_at_the_radius() {
+        let kinds = [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian { sigma : 0.5 },
+            FilterKind::MitchellNetravali { b : 1.0 / 3.0, c : 1.0 / 3.0 },
+            FilterKind::Lanczos,
+        ];
+        for kind in kinds {
+            let filter = Filter::new(kind, 2.0);
+            let centre = filter.evaluate(V2::zero());
+            assert!(centre > 0.0, "{:?}", kind);
+            assert!(filter.evaluate(V2::new(0.7, 0.3)) <= centre, "{:?}", kind);
+            assert_eq!(filter.evaluate(V2::new(2.1, 0.0)), 0.0, "{:?}", kind);
+            assert!(f32::abs(filter.evaluate(V2::new(0.0, 1.999))) < 0.01 || kind == FilterKind::Box, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn mitchell_has_negative_lobes() {
+        let filter = Filter::mitchell();
+        assert!(filter.evaluate(V2::new(1.5, 0.0)) < 0.0);
+        // the cubic is continuous where its two pieces meet
+        assert!(f32::abs(mitchell_1d(1.0 - 1e-4, 0.3, 0.3) - mitchell_1d(1.0 + 1e-4, 0.3, 0.3)) < 1e-3);
+    }
+}
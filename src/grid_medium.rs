@@ -0,0 +1,417 @@
+use std::io;
+use std::path::Path;
+use crate::vector::V3;
+use crate::sampler::{Sampler, hash, hash3, bits_to_unit};
+use crate::medium::{Medium, MediumSample, HenyeyGreenstein, component};
+use crate::cuboid::slab_interval;
+
+// Dense 3d grid of values, e.g. smoke density or how hot a fire burns. Values sit at the voxel
+// centres and are interpolated trilinearly in between. Points are in grid space, [0, 1]^3 across
+// the whole grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    pub nx : usize,
+    pub ny : usize,
+    pub nz : usize,
+    pub values : Vec<f32>, // x varies fastest, then y, then z
+}
+
+fn invalid(message : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl VoxelGrid {
+    pub fn new(nx : usize, ny : usize, nz : usize, values : Vec<f32>) -> Self {
+        assert!(nx > 0 && ny > 0 && nz > 0, "voxel grid needs at least one voxel along each axis");
+        assert_eq!(values.len(), nx * ny * nz, "voxel grid has {} values, expected {}x{}x{}", values.len(), nx, ny, nz);
+        VoxelGrid { nx, ny, nz, values }
+    }
+
+    // evaluates f at the centre of every voxel
+    pub fn from_fn(nx : usize, ny : usize, nz : usize, f : impl Fn(V3) -> f32) -> Self {
+        let mut values = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let p = V3::new((x as f32 + 0.5) / nx as f32, (y as f32 + 0.5) / ny as f32, (z as f32 + 0.5) / nz as f32);
+                    values.push(f(p));
+                }
+            }
+        }
+        Self::new(nx, ny, nz, values)
+    }
+
+    // a puffy ball of cloud, fractal noise eaten away towards the edges of the grid
+    pub fn cloud(resolution : usize, seed : u32) -> Self {
+        Self::from_fn(resolution, resolution, resolution, |p| {
+            let r = (p - V3::new(0.5, 0.5, 0.5)).magnitude() * 2.0;
+            f32::max(fractal_noise(p * 4.0, seed) + 0.6 - r, 0.0) * 2.0
+        })
+    }
+
+    // raw voxel files: the magic bytes VOXL, the resolution as three little endian u32 and then
+    // one little endian f32 per voxel in the same order as values
+    pub fn load(path : impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(bytes : &[u8]) -> io::Result<Self> {
+        if bytes.len() < 16 || &bytes[..4] != b"VOXL" {
+            return Err(invalid("not a voxel file"));
+        }
+        let word = |i : usize| [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+        let (nx, ny, nz) = (u32::from_le_bytes(word(4)) as usize, u32::from_le_bytes(word(8)) as usize, u32::from_le_bytes(word(12)) as usize);
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(invalid("voxel grid has no voxels"));
+        }
+        let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz)).ok_or_else(|| invalid("voxel grid too large"))?;
+        let size = count.checked_mul(4).and_then(|n| n.checked_add(16)).ok_or_else(|| invalid("voxel grid too large"))?;
+        if bytes.len() != size {
+            return Err(invalid("voxel file size doesn't match its resolution"));
+        }
+        let values = (0..count).map(|i| f32::from_le_bytes(word(16 + 4 * i))).collect();
+        Ok(Self::new(nx, ny, nz, values))
+    }
+
+    pub fn value(&self, x : usize, y : usize, z : usize) -> f32 {
+        self.values[(z * self.ny + y) * self.nx + x]
+    }
+
+    // trilinear interpolation at p in grid space, 0 outside the grid
+    pub fn lookup(&self, p : V3) -> f32 {
+        if !(0.0..=1.0).contains(&p.x) || !(0.0..=1.0).contains(&p.y) || !(0.0..=1.0).contains(&p.z) {
+            return 0.0;
+        }
+        // lower voxel index and fraction towards the next along one axis
+        let axis = |coordinate : f32, n : usize| {
+            let x = f32::clamp(coordinate * n as f32 - 0.5, 0.0, (n - 1) as f32);
+            let i = usize::min(x as usize, n.saturating_sub(2));
+            (i, usize::min(i + 1, n - 1), x - i as f32)
+        };
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+        let lerp = |a : f32, b : f32, t : f32| a + (b - a) * t;
+        let plane = |z : usize| {
+            lerp(lerp(self.value(x0, y0, z), self.value(x1, y0, z), fx), lerp(self.value(x0, y1, z), self.value(x1, y1, z), fx), fy)
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+
+    // largest value any lookup can return inside the grid space box from low to high
+    fn max_in(&self, low : V3, high : V3) -> f32 {
+        let range = |low : f32, high : f32, n : usize| {
+            let first = f32::max(f32::floor(low * n as f32 - 0.5), 0.0) as usize;
+            let last = f32::max(f32::ceil(high * n as f32 - 0.5), 0.0) as usize;
+            (usize::min(first, n - 1), usize::min(last, n - 1))
+        };
+        let (x0, x1) = range(low.x, high.x, self.nx);
+        let (y0, y1) = range(low.y, high.y, self.ny);
+        let (z0, z1) = range(low.z, high.z, self.nz);
+        let mut max = 0.0;
+        for z in z0..=z1 {
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    max = f32::max(max, self.value(x, y, z));
+                }
+            }
+        }
+        max
+    }
+}
+
+// value noise on the integer lattice, smoothly interpolated, in [0, 1)
+fn value_noise(p : V3, seed : u32) -> f32 {
+    let (x, y, z) = (f32::floor(p.x), f32::floor(p.y), f32::floor(p.z));
+    let lattice = |dx : i32, dy : i32, dz : i32| {
+        bits_to_unit(hash3((x as i32 + dx) as u32, (y as i32 + dy) as u32, hash((z as i32 + dz) as u32 ^ seed)))
+    };
+    let smooth = |t : f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (smooth(p.x - x), smooth(p.y - y), smooth(p.z - z));
+    let lerp = |a : f32, b : f32, t : f32| a + (b - a) * t;
+    let plane = |dz : i32| lerp(lerp(lattice(0, 0, dz), lattice(1, 0, dz), fx), lerp(lattice(0, 1, dz), lattice(1, 1, dz), fx), fy);
+    lerp(plane(0), plane(1), fz)
+}
+
+// four octaves of value noise, each twice as fine and half as strong, in [0, 1)
+pub fn fractal_noise(p : V3, seed : u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut p = p;
+    for octave in 0..4 {
+        sum += amplitude * value_noise(p, seed.wrapping_add(octave));
+        amplitude *= 0.5;
+        p *= 2.0;
+    }
+    sum / 0.9375
+}
+
+// cells along each axis of the coarse grid of majorants
+const MAJORANT_RESOLUTION : usize = 16;
+
+// stream of random numbers for tracking, which takes however many steps the density asks for.
+// seeded from a single sampler dimension so every other decision keeps its place in the sampler
+struct TrackingRng(u32);
+
+impl TrackingRng {
+    fn new(u : f32) -> Self {
+        TrackingRng(hash((u * 4294967296.0) as u32))
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 = hash(self.0.wrapping_add(0x9e3779b9));
+        bits_to_unit(self.0)
+    }
+}
+
+// A voxel grid of density stretched over the box from min to max, for clouds and smoke. The
+// coefficients are per unit of density. With emission the medium glows like fire: its absorbing
+// part gives off the emission grid's value times emission_color (Novák et al. 2018, "Monte Carlo
+// Methods for Volumetric Light Transport Simulation", as in pbrt-v4).
+pub struct GridMedium {
+    pub density : VoxelGrid,
+    pub min : V3,
+    pub max : V3,
+    pub absorption : V3,
+    pub scattering : V3,
+    pub g : f32,
+    pub emission : Option<VoxelGrid>,
+    pub emission_color : V3, // on the 0-255 scale, e.g. a blackbody color
+    // largest density in each cell of a coarse grid over the box, which bounds how dense the
+    // medium can get along a stretch of ray so tracking can take long steps through thin parts
+    majorants : Vec<f32>,
+}
+
+impl GridMedium {
+    pub fn new(density : VoxelGrid, min : V3, max : V3, absorption : V3, scattering : V3, g : f32) -> Self {
+        let n = MAJORANT_RESOLUTION;
+        let mut majorants = Vec::with_capacity(n * n * n);
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    let low = V3::new(x as f32, y as f32, z as f32) / n as f32;
+                    let high = V3::new((x + 1) as f32, (y + 1) as f32, (z + 1) as f32) / n as f32;
+                    majorants.push(density.max_in(low, high));
+                }
+            }
+        }
+        GridMedium {
+            density,
+            min,
+            max,
+            absorption,
+            scattering,
+            g,
+            emission : None,
+            emission_color : V3::zero(),
+            majorants,
+        }
+    }
+
+    pub fn with_emission(self, emission : VoxelGrid, color : V3) -> Self {
+        GridMedium {
+            emission : Some(emission),
+            emission_color : color,
+            ..self
+        }
+    }
+
+    fn to_grid(&self, point : V3) -> V3 {
+        let size = self.max - self.min;
+        let offset = point - self.min;
+        V3::new(offset.x / size.x, offset.y / size.y, offset.z / size.z)
+    }
+
+    fn extinction(&self) -> V3 {
+        self.absorption + self.scattering
+    }
+
+    // walks the majorant cells the ray passes up to t_max with a 3d DDA, and within each draws
+    // tentative collisions at exponentially distributed steps for that cell's majorant. collide
+    // gets each collision's distance and majorant and returns true to stop there
+    fn track(&self, origin : V3, dir : V3, t_max : f32, rng : &mut TrackingRng, mut collide : impl FnMut(f32, f32, &mut TrackingRng) -> bool) {
+        let sigma_t_max = self.extinction().max_component();
+        let size = self.max - self.min;
+        let o = self.to_grid(origin);
+        let d = V3::new(dir.x / size.x, dir.y / size.y, dir.z / size.z);
+        let (near, far) = slab_interval(o, d, V3::zero(), V3::new(1.0, 1.0, 1.0));
+        let (t_enter, t_exit) = (f32::max(near, 0.0), f32::min(far, t_max));
+        if t_enter >= t_exit || sigma_t_max <= 0.0 {
+            return;
+        }
+
+        let n = MAJORANT_RESOLUTION as i32;
+        let start = o + d * t_enter;
+        let mut cell = [0i32; 3];
+        let mut next = [f32::INFINITY; 3];
+        let mut delta = [0.0; 3];
+        let mut step = [0i32; 3];
+        for axis in 0..3 {
+            let p = component(start, axis);
+            let dir = component(d, axis);
+            cell[axis] = i32::clamp((p * n as f32) as i32, 0, n - 1);
+            if dir > 0.0 {
+                next[axis] = t_enter + ((cell[axis] + 1) as f32 / n as f32 - p) / dir;
+                delta[axis] = 1.0 / (n as f32 * dir);
+                step[axis] = 1;
+            }
+            else if dir < 0.0 {
+                next[axis] = t_enter + (cell[axis] as f32 / n as f32 - p) / dir;
+                delta[axis] = -1.0 / (n as f32 * dir);
+                step[axis] = -1;
+            }
+        }
+
+        let mut t = t_enter;
+        loop {
+            let axis = if next[0] <= next[1] && next[0] <= next[2] { 0 } else if next[1] <= next[2] { 1 } else { 2 };
+            let cell_end = f32::min(next[axis], t_exit);
+            let idx = ((cell[2] * n + cell[1]) * n + cell[0]) as usize;
+            let majorant = self.majorants[idx] * sigma_t_max;
+            if majorant > 0.0 {
+                let mut s = t;
+                loop {
+                    s -= f32::ln(1.0 - rng.next()) / majorant;
+                    if s >= cell_end {
+                        break;
+                    }
+                    if collide(s, majorant, rng) {
+                        return;
+                    }
+                }
+            }
+            if cell_end >= t_exit {
+                return;
+            }
+            t = cell_end;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= n {
+                return;
+            }
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+impl Medium for GridMedium {
+    // weighted delta tracking: each tentative collision is real with probability the average
+    // extinction over the majorant, and weights correct for colored media where the channels
+    // would have decided differently. emission is collected at every collision on the way
+    fn sample(&self, origin : V3, dir : V3, t_max : f32, sampler : &mut dyn Sampler) -> MediumSample {
+        let mut rng = TrackingRng::new(sampler.get_1d());
+        let ones = V3::new(1.0, 1.0, 1.0);
+        let mut result = MediumSample { scatter : None, weight : ones, emission : V3::zero() };
+        self.track(origin, dir, t_max, &mut rng, |t, majorant, rng| {
+            let point = origin + dir * t;
+            let density = self.density.lookup(self.to_grid(point));
+            let sigma_t = self.extinction() * density;
+            if let Some(emission) = &self.emission {
+                let glow = self.emission_color * emission.lookup(self.to_grid(point));
+                result.emission += result.weight.mul_elem(self.absorption * density).mul_elem(glow) / majorant;
+            }
+            let p_real = f32::min((sigma_t.x + sigma_t.y + sigma_t.z) / (3.0 * majorant), 1.0);
+            if rng.next() < p_real {
+                result.weight = result.weight.mul_elem(self.scattering * density) / (majorant * p_real);
+                result.scatter = Some(t);
+                return true;
+            }
+            result.weight = result.weight.mul_elem(ones * majorant - sigma_t) / (majorant * (1.0 - p_real));
+            false
+        });
+        result
+    }
+
+    // ratio tracking, every tentative collision keeps the share of the majorant that is null
+    fn transmittance(&self, origin : V3, dir : V3, distance : f32, sampler : &mut dyn Sampler) -> V3 {
+        let mut rng = TrackingRng::new(sampler.get_1d());
+        let ones = V3::new(1.0, 1.0, 1.0);
+        let mut transmittance = ones;
+        self.track(origin, dir, distance, &mut rng, |t, majorant, _| {
+            let density = self.density.lookup(self.to_grid(origin + dir * t));
+            transmittance = transmittance.mul_elem(ones - self.extinction() * (density / majorant));
+            transmittance.max_component() <= 0.0
+        });
+        transmittance
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        HenyeyGreenstein { g : self.g }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::mean_over_samples;
+    use crate::medium::beer_lambert;
+
+    #[test]
+    fn grids_interpolate_and_round_trip_through_files() {
+        let grid = VoxelGrid::new(2, 1, 1, vec![1.0, 3.0]);
+        assert_eq!(grid.lookup(V3::new(0.25, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.lookup(V3::new(0.5, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.lookup(V3::new(1.5, 0.5, 0.5)), 0.0);
+
+        let mut bytes = b"VOXL".to_vec();
+        for n in [2u32, 1, 1] {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        for v in [1.0f32, 3.0] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(VoxelGrid::parse(&bytes).unwrap(), grid);
+        assert!(VoxelGrid::parse(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        let header = |nx : u32, ny : u32, nz : u32| {
+            let mut bytes = b"VOXL".to_vec();
+            for n in [nx, ny, nz] {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            bytes
+        };
+        assert!(VoxelGrid::parse(&header(0, 4, 4)).is_err());
+        // the voxel count fits, the file size it implies doesn't
+        assert!(VoxelGrid::parse(&header(u32::MAX, u32::MAX, 1)).is_err());
+        assert!(VoxelGrid::parse(&header(u32::MAX, u32::MAX, u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn tracking_a_constant_grid_matches_beer_lambert() {
+        let medium = GridMedium::new(
+            VoxelGrid::from_fn(4, 4, 4, |_| 0.5),
+            V3::new(-1.0, -1.0, -1.0),
+            V3::new(1.0, 1.0, 1.0),
+            V3::new(0.4, 0.8, 1.2),
+            V3::new(0.6, 0.6, 0.6),
+            0.0,
+        );
+        let dir = V3::new(0.0, 0.0, 1.0);
+        // from outside the box, through all 2 units of it
+        let origin = V3::new(0.1, -0.2, -3.0);
+        let expected = beer_lambert(medium.extinction() * 0.5, 2.0);
+        // weighted delta tracking of colored media is much noisier than ratio tracking, hence
+        // the extra samples
+        let n = 32768;
+        let ratio = mean_over_samples(n, |sampler| medium.transmittance(origin, dir, f32::INFINITY, sampler));
+        let through = mean_over_samples(n, |sampler| {
+            let sample = medium.sample(origin, dir, f32::INFINITY, sampler);
+            if sample.scatter.is_none() { sample.weight } else { V3::zero() }
+        });
+        assert!((ratio - expected).magnitude() < 0.01, "{:?} {:?}", ratio, expected);
+        assert!((through - expected).magnitude() < 0.02, "{:?} {:?}", through, expected);
+    }
+
+    #[test]
+    fn glowing_grids_give_off_light() {
+        let medium = GridMedium::new(VoxelGrid::from_fn(2, 2, 2, |_| 1.0), V3::zero(), V3::new(1.0, 1.0, 1.0), V3::new(1.0, 1.0, 1.0), V3::zero(), 0.0)
+            .with_emission(VoxelGrid::from_fn(2, 2, 2, |_| 1.0), V3::new(100.0, 50.0, 10.0));
+        let emitted = mean_over_samples(4096, |sampler| medium.sample(V3::new(0.5, 0.5, -1.0), V3::new(0.0, 0.0, 1.0), f32::INFINITY, sampler).emission);
+        // a purely absorbing slab one unit thick lets out (1 - e^-1) of its emission
+        let expected = 100.0 * (1.0 - f32::exp(-1.0));
+        assert!(f32::abs(emitted.x - expected) < 1.5, "{:?}", emitted);
+        assert!(VoxelGrid::cloud(8, 1).values.iter().any(|&v| v > 0.0));
+    }
+}
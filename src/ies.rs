@@ -0,0 +1,205 @@
+use std::io;
+use std::path::Path;
+
+// Angular distribution of a luminaire from an IES LM-63 photometric file, in type C photometry:
+// vertical angles run from 0 straight down the fixture's axis to 180 straight up, horizontal
+// angles turn around that axis. Candela values are absolute, already scaled by the file's
+// multiplier and ballast factor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IesProfile {
+    pub vertical_angles : Vec<f32>, // degrees, increasing
+    pub horizontal_angles : Vec<f32>, // degrees, increasing from 0
+    pub candela : Vec<Vec<f32>>, // one row of vertical samples per horizontal angle
+}
+
+fn invalid(message : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// a count read from the file as a number, which has to be whole and can't ask for more than the
+// total values in the file, so nothing gets allocated for data that isn't there
+fn count(value : f32, total : usize) -> io::Result<usize> {
+    if value >= 0.0 && value.fract() == 0.0 && value <= total as f32 {
+        Ok(value as usize)
+    }
+    else {
+        Err(invalid("bad count in ies file"))
+    }
+}
+
+// angles have to increase strictly for the lookups to interpolate between them
+fn increasing(angles : &[f32]) -> io::Result<()> {
+    if angles.windows(2).all(|pair| pair[0] < pair[1]) && angles.iter().all(|angle| angle.is_finite()) {
+        Ok(())
+    }
+    else {
+        Err(invalid("ies angles don't increase"))
+    }
+}
+
+impl IesProfile {
+    pub fn load(path : impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text : &str) -> io::Result<Self> {
+        // keywords come first and end with the TILT line, everything after is whitespace or comma
+        // separated numbers
+        let mut lines = text.lines();
+        let tilt = lines.by_ref()
+            .find(|line| line.trim_start().starts_with("TILT"))
+            .ok_or_else(|| invalid("ies file has no TILT line"))?;
+        let values = lines
+            .flat_map(|line| line.split(|c : char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f32>().map_err(|_| invalid("bad number in ies file")))
+            .collect::<io::Result<Vec<f32>>>()?;
+        let total = values.len();
+        let mut numbers = values.into_iter();
+        let mut next = || numbers.next().ok_or_else(|| invalid("ies file ends early"));
+
+        // lamp tilt tables only matter for fixtures mounted at an angle, which we skip over
+        if tilt.trim() == "TILT=INCLUDE" {
+            let _geometry = next()?;
+            let count = count(next()?, total / 2)?;
+            for _ in 0..2 * count {
+                next()?;
+            }
+        }
+        else if tilt.trim() != "TILT=NONE" {
+            return Err(invalid("tilt files are not supported"));
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = count(next()?, total)?;
+        let horizontal_count = count(next()?, total)?;
+        let photometric_type = next()?;
+        let _units = next()?;
+        let _dimensions = [next()?, next()?, next()?];
+        let ballast_factor = next()?;
+        let _future_use = next()?;
+        let _watts = next()?;
+        if photometric_type != 1.0 {
+            return Err(invalid("only type C photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid("ies file has no candela values"));
+        }
+        if vertical_count.checked_mul(horizontal_count).is_none_or(|values| values > total) {
+            return Err(invalid("ies file ends early"));
+        }
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<io::Result<Vec<f32>>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<io::Result<Vec<f32>>>()?;
+        increasing(&vertical_angles)?;
+        increasing(&horizontal_angles)?;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count).map(|_| next().map(|value| value * multiplier * ballast_factor));
+            candela.push(row.collect::<io::Result<Vec<f32>>>()?);
+        }
+        Ok(IesProfile { vertical_angles, horizontal_angles, candela })
+    }
+
+    // brightest direction, for normalising profiles
+    pub fn max_candela(&self) -> f32 {
+        self.candela.iter().flatten().fold(0.0, |max, &value| f32::max(max, value))
+    }
+
+    // candela towards vertical angle from the axis and horizontal angle around it, both in degrees.
+    // files only store the part of the distribution their symmetry doesn't repeat: a single
+    // horizontal angle is round, a last angle of 90 mirrors quadrants and 180 mirrors the halves
+    pub fn candela(&self, vertical : f32, horizontal : f32) -> f32 {
+        let horizontal = horizontal.rem_euclid(360.0);
+        let last = *self.horizontal_angles.last().unwrap();
+        let horizontal = if self.horizontal_angles.len() == 1 {
+            0.0
+        }
+        else if last <= 90.0 {
+            let h = horizontal % 180.0;
+            if h > 90.0 { 180.0 - h } else { h }
+        }
+        else if last <= 180.0 && horizontal > 180.0 {
+            360.0 - horizontal
+        }
+        else {
+            horizontal
+        };
+
+        let (h0, h1, th) = bracket(&self.horizontal_angles, horizontal);
+        let (v0, v1, tv) = bracket(&self.vertical_angles, vertical);
+        // a profile that stops short of 180 sends no light beyond its last vertical angle
+        if vertical > *self.vertical_angles.last().unwrap() || vertical < self.vertical_angles[0] {
+            return 0.0;
+        }
+        let lerp = |row : &Vec<f32>| row[v0] + (row[v1] - row[v0]) * tv;
+        lerp(&self.candela[h0]) + (lerp(&self.candela[h1]) - lerp(&self.candela[h0])) * th
+    }
+}
+
+// indices around x in the sorted angles and how far x is between them, clamped at the ends
+fn bracket(angles : &[f32], x : f32) -> (usize, usize, f32) {
+    let upper = angles.partition_point(|&angle| angle <= x);
+    if upper == 0 {
+        return (0, 0, 0.0);
+    }
+    if upper == angles.len() {
+        return (upper - 1, upper - 1, 0.0);
+    }
+    let (a, b) = (angles[upper - 1], angles[upper]);
+    (upper - 1, upper, (x - a) / (b - a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWNLIGHT : &str = "IESNA:LM-63-2002
+[TEST] downlight
+[MANUFAC] nobody
+TILT=NONE
+1 -1 2.0 3 2
+1 2 0.0 0.0 0.0
+1.0 1.0 20
+0 45 90
+0 90
+100 50 0
+200, 100, 0
+";
+
+    #[test]
+    fn parses_and_interpolates_a_profile() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(profile.max_candela(), 400.0);
+        assert_eq!(profile.candela(0.0, 0.0), 200.0);
+        assert_eq!(profile.candela(22.5, 0.0), 150.0);
+        assert_eq!(profile.candela(0.0, 45.0), 300.0);
+        // quadrant symmetry mirrors 90..180 back onto 90..0
+        assert_eq!(profile.candela(0.0, 135.0), 300.0);
+        assert_eq!(profile.candela(0.0, 270.0), 400.0);
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert!(IesProfile::parse("no tilt here").is_err());
+        assert!(IesProfile::parse("TILT=NONE\n1 -1 1 3 1").is_err());
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        let with = |from : &str, to : &str| IesProfile::parse(&DOWNLIGHT.replacen(from, to, 1));
+        assert!(with("TILT=NONE", "TILT=INCLUDE\n1 1e30").is_err());
+        // counts have to be whole, not negative and fit in the file
+        assert!(with("2.0 3 2", "2.0 -3 2").is_err());
+        assert!(with("2.0 3 2", "2.0 2.5 2").is_err());
+        assert!(with("2.0 3 2", "2.0 3 1e12").is_err());
+        assert!(with("2.0 3 2", "2.0 40000 40000").is_err());
+        // angles have to increase
+        assert!(with("0 45 90", "0 45 45").is_err());
+        assert!(with("0 90\n", "90 0\n").is_err());
+    }
+}
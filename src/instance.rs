@@ -0,0 +1,224 @@
+use std::sync::Arc;
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::shape::{RayTraceShape, area_to_solid_angle};
+use crate::material::Material;
+use crate::light_tree::LightBounds;
+use crate::transform::{Transform, AnimatedTransform};
+
+// Places a shared shape in the world through a transform. The shape itself lives in object space,
+// so the same geometry can be instanced many times while only being stored once. With an animation
+// the transform follows the keyframes over time instead, which blurs the shape across the shutter.
+#[derive(Clone)]
+pub struct Instance {
+    pub shape : Arc<dyn RayTraceShape>,
+    pub transform : Transform,
+    pub animation : Option<AnimatedTransform>,
+}
+
+impl Instance {
+    pub fn new(shape : Arc<dyn RayTraceShape>, transform : Transform) -> Self {
+        Instance {
+            shape,
+            transform,
+            animation : None,
+        }
+    }
+
+    // area() only looks at the first pose, so the animation may move and turn the shape but has
+    // to keep its scale for that area to hold over the whole shutter
+    pub fn animated(shape : Arc<dyn RayTraceShape>, animation : AnimatedTransform) -> Self {
+        let scale = animation.keyframes[0].scale;
+        assert!(animation.keyframes.iter().all(|keyframe| keyframe.scale == scale), "animated instances can't change their scale");
+        Instance {
+            shape,
+            transform : animation.transform_at(0.0),
+            animation : Some(animation),
+        }
+    }
+
+    pub fn transform_at(&self, time : f32) -> Transform {
+        match &self.animation {
+            Some(animation) => animation.transform_at(time),
+            None => self.transform,
+        }
+    }
+}
+
+// how much transform stretches the surface around a point with the object space normal n: a patch
+// of area dA in object space covers |det M| |M^-T n| dA in the world (Nanson's formula)
+fn area_scale(transform : &Transform, normal : V3) -> f32 {
+    let det = transform.matrix.to_matrix3().determinant();
+    f32::abs(det) * transform.transform_normal(normal).magnitude()
+}
+
+impl RayTraceShape for Instance {
+    fn intersect(&self, ray : Ray) -> Option<f32> {
+        self.intersect_part(ray).map(|(t, _)| t)
+    }
+
+    fn intersect_part(&self, ray : Ray) -> Option<(f32, usize)> {
+        let mut local = self.transform_at(ray.time).inverted().transform_ray(&ray);
+
+        // shapes expect unit directions, so normalize and rescale the hit distance back to world t
+        let dir_len = local.dir.magnitude();
+        if dir_len < 1e-8 {
+            return None;
+        }
+        local.dir /= dir_len;
+        self.shape.intersect_part(local).map(|(t, part)| (t / dir_len, part))
+    }
+
+    fn normal_at(&self, point : V3, time : f32) -> V3 {
+        let transform = self.transform_at(time);
+        let local_point = transform.inverse.transform_point(point);
+        transform.transform_normal(self.shape.normal_at(local_point, time)).normalized()
+    }
+
+    fn part_normal(&self, point : V3, part : usize, time : f32) -> V3 {
+        let transform = self.transform_at(time);
+        let local_point = transform.inverse.transform_point(point);
+        transform.transform_normal(self.shape.part_normal(local_point, part, time)).normalized()
+    }
+
+    fn material(&self) -> Material {
+        self.shape.material()
+    }
+
+    // exact for rotations, translations and uniform scales. other scales stretch the surface
+    // unevenly, which this approximates with the average scale factor. only the light tree uses
+    // it to weigh power, light sampling goes by the exact stretch at each point. animated instances
+    // keep their scale, so the first pose stands for all of them
+    fn area(&self) -> f32 {
+        let det = self.transform.matrix.to_matrix3().determinant();
+        self.shape.area() * f32::powf(f32::abs(det), 2.0 / 3.0)
+    }
+
+    fn sample_point(&self, u : V2, time : f32) -> (V3, V3) {
+        let transform = self.transform_at(time);
+        let (point, normal) = self.shape.sample_point(u, time);
+        (transform.transform_point(point), transform.transform_normal(normal).normalized())
+    }
+
+    // samples the shape uniformly in object space. the transform stretches that into a density of
+    // 1 / (object area * area_scale) per unit of world area, which varies over the surface under
+    // non uniform scales
+    fn sample_towards(&self, reference : V3, u : V2, time : f32) -> Option<(V3, V3, f32)> {
+        let transform = self.transform_at(time);
+        let (local_point, local_normal) = self.shape.sample_point(u, time);
+        let point = transform.transform_point(local_point);
+        let normal = transform.transform_normal(local_normal).normalized();
+        let area_pdf = 1.0 / (self.shape.area() * area_scale(&transform, local_normal));
+        let pdf = area_to_solid_angle(area_pdf, reference, point, normal);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((point, normal, pdf))
+    }
+
+    fn pdf_towards(&self, reference : V3, point : V3, normal : V3, time : f32) -> f32 {
+        let transform = self.transform_at(time);
+        let local_normal = self.shape.normal_at(transform.inverse.transform_point(point), time);
+        let area_pdf = 1.0 / (self.shape.area() * area_scale(&transform, local_normal));
+        area_to_solid_angle(area_pdf, reference, point, normal)
+    }
+
+    // bounds the transformed box of the shape's bounds. moving instances sweep through space over
+    // the shutter, so only their power is known
+    fn light_bounds(&self) -> LightBounds {
+        let local = self.shape.light_bounds();
+        if self.animation.is_some() {
+            return LightBounds::unbounded(local.power * self.area() / self.shape.area());
+        }
+        let mut min = V3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+        for corner in 0..8 {
+            let pick = |bit : usize, low : f32, high : f32| if corner & bit == 0 { low } else { high };
+            let point = V3::new(pick(1, local.min.x, local.max.x), pick(2, local.min.y, local.max.y), pick(4, local.min.z, local.max.z));
+            let point = self.transform.transform_point(point);
+            min = min.min_elem(point);
+            max = max.max_elem(point);
+        }
+        // non uniform scales bend normals unevenly, so only a cone holding everything is safe
+        let cos_theta_o = if f32::abs(local.cos_theta_o - 1.0) < 1e-6 || local.cos_theta_o <= -1.0 { local.cos_theta_o } else { -1.0 };
+        LightBounds {
+            min,
+            max,
+            axis : self.transform.transform_normal(local.axis).normalized(),
+            cos_theta_o,
+            power : local.power * self.area() / self.shape.area(),
+            ..local
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Col3;
+    use crate::sphere::Sphere;
+    use crate::quaternion::Quaternion;
+    use crate::transform::Keyframe;
+    use crate::sampler::mean_over_samples;
+    use std::f32::consts::PI;
+
+    fn unit_sphere() -> Arc<dyn RayTraceShape> {
+        Arc::new(Sphere { origin : V3::zero(), radius : 1.0, material : Material::new(0.0, Col3::black()) })
+    }
+
+    #[test]
+    fn hits_come_back_in_world_distances() {
+        let ray = Ray::new(V3::zero(), V3::new(0.0, 0.0, 1.0), 0);
+        // the object space ray is half as long, the hit distance has to be scaled back up
+        let uniform = Instance::new(unit_sphere(), Transform::scaling(V3::new(2.0, 2.0, 2.0)).then(&Transform::translation(V3::new(0.0, 0.0, 10.0))));
+        assert!(f32::abs(uniform.intersect(ray).unwrap() - 8.0) < 1e-4);
+        // stretched along the ray only
+        let stretched = Instance::new(unit_sphere(), Transform::scaling(V3::new(1.0, 1.0, 3.0)).then(&Transform::translation(V3::new(0.0, 0.0, 10.0))));
+        let t = stretched.intersect(ray).unwrap();
+        assert!(f32::abs(t - 7.0) < 1e-4);
+        let normal = stretched.normal_at(ray.origin + ray.dir * t, 0.0);
+        assert!((normal - V3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn animated_instances_move_with_the_ray_time() {
+        let up = V3::new(0.0, 1.0, 0.0);
+        let instance = Instance::animated(unit_sphere(), AnimatedTransform::new(vec![
+            Keyframe::new(0.0, V3::new(0.0, 0.0, 5.0), Quaternion::identity(), V3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(1.0, V3::new(0.0, 0.0, 9.0), Quaternion::from_axis_angle(up, 1.0), V3::new(1.0, 1.0, 1.0)),
+        ]));
+        let mut ray = Ray::new(V3::zero(), V3::new(0.0, 0.0, 1.0), 0);
+        assert!(f32::abs(instance.intersect(ray).unwrap() - 4.0) < 1e-4);
+        ray.time = 0.5;
+        assert!(f32::abs(instance.intersect(ray).unwrap() - 6.0) < 1e-4);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't change their scale")]
+    fn animated_instances_keep_their_scale() {
+        Instance::animated(unit_sphere(), AnimatedTransform::new(vec![
+            Keyframe::new(0.0, V3::zero(), Quaternion::identity(), V3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(1.0, V3::zero(), Quaternion::identity(), V3::new(2.0, 2.0, 2.0)),
+        ]));
+    }
+
+    #[test]
+    fn light_sampling_follows_non_uniform_stretch() {
+        // a sphere stretched into a prolate spheroid with semi axes 1, 1 and 3
+        let instance = Instance::new(unit_sphere(), Transform::scaling(V3::new(1.0, 1.0, 3.0)).then(&Transform::translation(V3::new(0.0, 0.0, 10.0))));
+        // far enough away to see just about half of it
+        let reference = V3::new(0.0, 1000.0, 10.0);
+        let area = mean_over_samples(4096, |sampler| {
+            let Some((point, normal, pdf)) = instance.sample_towards(reference, sampler.get_2d(), 0.0) else { return 0.0 };
+            assert!(f32::abs(instance.pdf_towards(reference, point, normal, 0.0) - pdf) < 1e-3 * pdf);
+            // back to a density per unit of world area, whose inverse averages to the area
+            let offset = point - reference;
+            let area_pdf = pdf * -offset.normalized().dot(normal) / offset.magnitude_squared();
+            1.0 / area_pdf
+        });
+        // only the side facing the reference gets sampled
+        let e = f32::sqrt(1.0 - 1.0 / 9.0);
+        let spheroid = 2.0 * PI * (1.0 + 3.0 / e * f32::asin(e));
+        assert!(f32::abs(area - 0.5 * spheroid) < 0.01 * spheroid, "{} {}", area, spheroid);
+    }
+}
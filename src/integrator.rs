@@ -0,0 +1,354 @@
+use crate::vector::V3;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::material::Material;
+use crate::light::power_heuristic;
+use crate::medium::HenyeyGreenstein;
+use crate::camera::Camera;
+
+// discrete ray stepping is switched off until non linear space is modelled
+const DISCRETE_STEPPING : bool = false;
+
+// Computes the light arriving along a ray from camera, on the 0-255 scale. The camera's depth
+// limits apply, which for a stereo eye isn't scene.camera. Any random decisions draw from the
+// sampler, which the renderer has already started on the ray's pixel sample.
+pub trait Integrator {
+    fn radiance(&self, scene : &Scene, camera : &Camera, ray : &mut Ray, sampler : &mut dyn Sampler) -> V3;
+}
+
+// Which integrator a render uses, see Camera::integrator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    // full global illumination
+    Path,
+    // mirror reflections and direct light only
+    Whitted,
+    // how open the surroundings of the visible point are, rays further than radius don't occlude
+    AmbientOcclusion { radius : f32 },
+    // a single bounce of light from the lights off the visible surface
+    Direct,
+}
+
+impl IntegratorKind {
+    pub fn build(self) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::new(PathIntegrator),
+            IntegratorKind::Whitted => Box::new(WhittedIntegrator),
+            IntegratorKind::AmbientOcclusion { radius } => Box::new(AmbientOcclusionIntegrator { radius }),
+            IntegratorKind::Direct => Box::new(DirectLightingIntegrator),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            IntegratorKind::Path => IntegratorKind::Whitted,
+            IntegratorKind::Whitted => IntegratorKind::AmbientOcclusion { radius : 2.0 },
+            IntegratorKind::AmbientOcclusion { .. } => IntegratorKind::Direct,
+            IntegratorKind::Direct => IntegratorKind::Path,
+        }
+    }
+
+    // parses the names accepted on the command line
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "path" => Some(IntegratorKind::Path),
+            "whitted" => Some(IntegratorKind::Whitted),
+            "ao" => Some(IntegratorKind::AmbientOcclusion { radius : 2.0 }),
+            "direct" => Some(IntegratorKind::Direct),
+            _ => None,
+        }
+    }
+}
+
+// normal at the hit point, flipped to face back along the incoming ray
+fn facing_normal(normal : V3, dir : V3) -> V3 {
+    if normal.dot(dir) > 0.0 { -normal } else { normal }
+}
+
+// cosine weighted direction around normal, the ideal importance sampling for diffuse surfaces
+fn diffuse_direction(normal : V3, sampler : &mut dyn Sampler) -> V3 {
+    sampling::to_world(sampling::cosine_hemisphere(sampler.get_2d()), normal).normalized()
+}
+
+// What scatters light at a path vertex, a surface or a medium the path is travelling through.
+#[derive(Clone, Copy)]
+enum Scatter<'a> {
+    Surface { material : &'a Material, normal : V3 },
+    Medium(HenyeyGreenstein),
+}
+
+impl Scatter<'_> {
+    // share of light arriving from wi that leaves towards wo, cosine included for surfaces, and
+    // the density with which sampling the bsdf or phase function finds wi
+    fn evaluate(&self, wo : V3, wi : V3) -> (V3, f32) {
+        match self {
+            Scatter::Surface { material, normal } => (material.eval_bsdf(wo, wi, *normal), material.pdf(wo, wi, *normal)),
+            Scatter::Medium(phase) => {
+                let p = phase.p(wo, wi);
+                (V3::new(p, p, p), p)
+            }
+        }
+    }
+}
+
+// Light arriving at point straight from one sampled light, scattered towards wo. Shadow rays start
+// out in volume and are dimmed by the media they pass. With mis the result is weighted against
+// the chance that bsdf sampling finds the same light, for integrators that also count emission hit
+// by bounce rays.
+#[allow(clippy::too_many_arguments)]
+fn sample_direct(scene : &Scene, point : V3, wo : V3, scatter : &Scatter, time : f32, volume : Option<usize>, sampler : &mut dyn Sampler, mis : bool) -> V3 {
+    let Some(light) = scene.sample_light(point, time, sampler) else { return V3::zero() };
+    if light.pdf <= 0.0 {
+        return V3::zero();
+    }
+    let (f, scatter_pdf) = scatter.evaluate(wo, light.dir);
+    if f.max_component() <= 0.0 {
+        return V3::zero();
+    }
+    let transmittance = scene.transmittance(point, light.dir, light.distance, time, volume, sampler);
+    if transmittance.max_component() <= 0.0 {
+        return V3::zero();
+    }
+    let weight = if light.delta || !mis {
+        1.0
+    }
+    else {
+        power_heuristic(light.pdf, scatter_pdf)
+    };
+    f.mul_elem(transmittance).mul_elem(light.radiance) * (weight / light.pdf)
+}
+
+// russian roulette: past roulette_depth, dark paths are likely to end here. survivors are
+// weighted up by 1 / p, so the expected result is unchanged
+fn survives_roulette(throughput : &mut V3, bounces : u16, camera : &Camera, sampler : &mut dyn Sampler) -> bool {
+    if bounces < camera.roulette_depth {
+        return true;
+    }
+    let survival = f32::min(throughput.max_component(), 1.0);
+    if sampler.get_1d() >= survival {
+        return false;
+    }
+    *throughput /= survival;
+    true
+}
+
+// Unidirectional path tracing with next event estimation. At every diffuse vertex one light is
+// sampled directly, and emission that bounce rays run into is counted as well, each weighted by
+// the power heuristic so whichever strategy is better at finding a light dominates. Inside
+// participating media the path samples a free flight distance on every segment and may scatter
+// in the medium before reaching the next surface.
+pub struct PathIntegrator;
+
+impl Integrator for PathIntegrator {
+    // a loop rather than recursion, so paths hundreds of bounces deep don't grow the stack
+    fn radiance(&self, scene : &Scene, camera : &Camera, ray : &mut Ray, sampler : &mut dyn Sampler) -> V3 {
+        let mut radiance = V3::zero();
+        // fraction of the light at the current vertex that makes it back to the camera
+        let mut throughput = V3::new(1.0, 1.0, 1.0);
+        // camera rays see emitters directly, same as after a mirror bounce
+        let mut specular = true;
+        let mut bsdf_pdf = 0.0;
+        let mut bounces = 0;
+        // where the path last scattered, which light densities for mis are measured from. the ray
+        // origin moves on without scattering when it crosses into or out of a volume
+        let mut vertex = ray.origin;
+        let mut volume = scene.volume_containing(ray.origin, ray.time);
+        ray.dir = ray.dir.normalized();
+        loop {
+            let hit = if bounces == 0 { scene.closest_camera_intersection(ray) } else { scene.closest_intersection(ray) };
+            let surface_t = hit.map_or(f32::INFINITY, |(t, _, _)| t);
+            let boundary = scene.next_volume_boundary(ray).filter(|&(t, _, _)| t < surface_t);
+            let segment = boundary.map_or(surface_t, |(t, _, _)| t);
+
+            if let Some(medium) = scene.medium_in(volume) {
+                let event = medium.sample(ray.origin, ray.dir, segment, sampler);
+                radiance += throughput.mul_elem(event.emission);
+                throughput = throughput.mul_elem(event.weight);
+                if throughput.max_component() <= 0.0 {
+                    break;
+                }
+                if let Some(t) = event.scatter {
+                    if bounces >= camera.bounce_depth {
+                        break;
+                    }
+                    let point = ray.origin + ray.dir * t;
+                    let wo = -ray.dir;
+                    let phase = medium.phase();
+                    radiance += throughput.mul_elem(sample_direct(scene, point, wo, &Scatter::Medium(phase), ray.time, volume, sampler, true));
+                    // sampling the phase function exactly leaves the throughput as it is
+                    let (dir, pdf) = phase.sample(wo, sampler.get_2d());
+                    specular = false;
+                    bsdf_pdf = pdf;
+                    vertex = point;
+                    ray.origin = point;
+                    ray.dir = dir;
+                    bounces += 1;
+                    if !survives_roulette(&mut throughput, bounces, camera, sampler) {
+                        break;
+                    }
+                    continue;
+                }
+            }
+            if let Some((t, idx, entering)) = boundary {
+                ray.origin += ray.dir * t;
+                volume = if entering { Some(idx) } else { None };
+                continue;
+            }
+
+            let Some((interx, shape, part)) = hit else {
+                // escaped, the environment is lit the same way as emissive shapes
+                let weight = if specular { 1.0 } else { power_heuristic(bsdf_pdf, scene.environment_pdf(vertex, ray.dir)) };
+                radiance += throughput.mul_elem(scene.background(ray.dir)) * weight;
+                break;
+            };
+            if DISCRETE_STEPPING && interx > camera.step_len * 5.0 {
+                ray.origin += ray.dir * camera.step_len * 0.95;
+                if ray.steps_remaining == 0 {
+                    break;
+                }
+                ray.steps_remaining -= 1;
+                continue;
+            }
+            ray.steps_remaining = camera.max_steps;
+            let point = ray.origin + ray.dir * interx;
+            let wo = -ray.dir;
+            let normal = shape.part_normal(point, part, ray.time);
+            let material = shape.material();
+
+            if material.is_emissive() && normal.dot(wo) > 0.0 {
+                let weight = if specular {
+                    1.0
+                }
+                else {
+                    power_heuristic(bsdf_pdf, scene.light_pdf(vertex, point, normal, shape, ray.time))
+                };
+                radiance += throughput.mul_elem(material.emission) * weight;
+            }
+            if bounces >= camera.bounce_depth {
+                break;
+            }
+
+            let normal = facing_normal(normal, ray.dir);
+            let surface = Scatter::Surface { material : &material, normal };
+            radiance += throughput.mul_elem(sample_direct(scene, point, wo, &surface, ray.time, volume, sampler, true));
+            let Some(bsdf) = material.sample_bsdf(wo, normal, sampler) else { break };
+            throughput = throughput.mul_elem(bsdf.weight);
+            specular = bsdf.specular;
+            bsdf_pdf = bsdf.pdf;
+            vertex = point;
+            ray.origin = point;
+            ray.dir = bsdf.dir;
+            bounces += 1;
+            if !survives_roulette(&mut throughput, bounces, camera, sampler) {
+                break;
+            }
+        }
+        radiance
+    }
+}
+
+// Follows perfect mirror reflections up to bounce_depth. Each surface keeps smoothness of the
+// light for the reflection and shades the rest with one light sample, which gives hard shadows
+// from point lights and noisy soft ones from emissive shapes.
+pub struct WhittedIntegrator;
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, scene : &Scene, camera : &Camera, ray : &mut Ray, sampler : &mut dyn Sampler) -> V3 {
+        let mut radiance = V3::zero();
+        let mut throughput = V3::new(1.0, 1.0, 1.0);
+        for depth in 0..=camera.bounce_depth {
+            let hit = if depth == 0 { scene.closest_camera_intersection(ray) } else { scene.closest_intersection(ray) };
+            let Some((interx, shape, part)) = hit else {
+                radiance += throughput.mul_elem(scene.background(ray.dir));
+                break;
+            };
+            let point = ray.origin + ray.dir * interx;
+            let wo = -ray.dir.normalized();
+            let normal = shape.part_normal(point, part, ray.time);
+            let material = shape.material();
+            if material.is_emissive() && normal.dot(wo) > 0.0 {
+                radiance += throughput.mul_elem(material.emission);
+            }
+            let normal = facing_normal(normal, ray.dir);
+            let volume = scene.volume_containing(point + normal * 0.001, ray.time);
+            radiance += throughput.mul_elem(sample_direct(scene, point, wo, &Scatter::Surface { material : &material, normal }, ray.time, volume, sampler, false));
+            if material.smoothness <= 0.0 || depth == camera.bounce_depth {
+                break;
+            }
+            throughput = throughput.mul_elem(material.reflectance()) * material.smoothness;
+            ray.origin = point;
+            ray.dir = (-wo).reflect(normal);
+        }
+        radiance
+    }
+}
+
+// White where the hemisphere above the visible point is open and darker where nearby geometry
+// blocks it. Takes one cosine weighted occlusion ray per camera sample.
+pub struct AmbientOcclusionIntegrator {
+    pub radius : f32,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, scene : &Scene, _camera : &Camera, ray : &mut Ray, sampler : &mut dyn Sampler) -> V3 {
+        let Some((interx, shape, part)) = scene.closest_camera_intersection(ray) else { return V3::zero() };
+        let point = ray.origin + ray.dir * interx;
+        let normal = facing_normal(shape.part_normal(point, part, ray.time), ray.dir);
+        let probe = Ray { origin : point, dir : diffuse_direction(normal, sampler), ..*ray };
+        match scene.closest_intersection(&probe) {
+            Some((t, _, _)) if t < self.radius => V3::zero(),
+            _ => V3::new(255.0, 255.0, 255.0),
+        }
+    }
+}
+
+// Emission seen directly plus light reaching the camera after exactly one bounce, found by
+// sampling the lights from the visible point.
+pub struct DirectLightingIntegrator;
+
+impl Integrator for DirectLightingIntegrator {
+    fn radiance(&self, scene : &Scene, _camera : &Camera, ray : &mut Ray, sampler : &mut dyn Sampler) -> V3 {
+        let Some((interx, shape, part)) = scene.closest_camera_intersection(ray) else { return scene.background(ray.dir) };
+        let point = ray.origin + ray.dir * interx;
+        let wo = -ray.dir.normalized();
+        let normal = shape.part_normal(point, part, ray.time);
+        let material = shape.material();
+        let mut radiance = V3::zero();
+        if material.is_emissive() && normal.dot(wo) > 0.0 {
+            radiance += material.emission;
+        }
+        let normal = facing_normal(normal, ray.dir);
+        // shadow rays leave on the side the camera ray came from
+        let volume = scene.volume_containing(point + normal * 0.001, ray.time);
+        radiance + sample_direct(scene, point, wo, &Scatter::Surface { material : &material, normal }, ray.time, volume, sampler, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use crate::sampling::cosine_hemisphere;
+    use crate::vector::V2;
+
+    #[test]
+    fn diffuse_directions_stay_above_the_surface() {
+        let normal = V3::new(0.3, -1.0, 0.2).normalized();
+        let mut sampler = SamplerKind::Sobol.build(64, 1);
+        for i in 0..64 {
+            sampler.start_pixel_sample(0, 0, i);
+            let dir = diffuse_direction(normal, sampler.as_mut());
+            assert!(dir.dot(normal) >= 0.0);
+            assert!(f32::abs(dir.magnitude() - 1.0) < 1e-4);
+        }
+        assert!(f32::abs(cosine_hemisphere(V2::new(0.5, 0.5)).z - 1.0) < 1e-6);
+    }
+
+    #[test]
+    fn names_parse() {
+        assert_eq!(IntegratorKind::from_name("whitted"), Some(IntegratorKind::Whitted));
+        assert_eq!(IntegratorKind::from_name("photon"), None);
+    }
+}
@@ -1,15 +1,243 @@
+use std::f32::consts::PI;
+use crate::vector::{V2, V3};
+use crate::ies::IesProfile;
+use crate::light_tree::LightBounds;
+use crate::adaptive::luminance;
+use crate::sampling;
 
+// Light arriving at a point from one sampled position on a light source.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    pub dir : V3, // unit vector from the shaded point towards the light
+    pub distance : f32, // to the sampled position, infinite for directional lights
+    pub radiance : V3, // arriving along dir, on the 0-255 scale
+    pub pdf : f32, // solid angle density of having picked dir, 1 for delta lights
+    pub delta : bool, // lights rays can't hit, like point and directional lights, are only reached by sampling them
+}
+
+// Lights that aren't part of the geometry, so camera and bounce rays never hit them and they can
+// only contribute through light sampling. Emissive shapes are lights as well, see Scene.
+pub trait Light : Send + Sync {
+    fn sample(&self, point : V3, u : V2) -> Option<LightSample>;
+    // extent and power for the light tree, LightBounds::unbounded for lights it can't place
+    fn bounds(&self) -> LightBounds;
+}
+
+// Infinitely small light shining equally in all directions, falling off with the squared distance.
+pub struct PointLight {
+    pub position : V3,
+    pub intensity : V3,
+}
+
+impl Light for PointLight {
+    fn sample(&self, point : V3, _u : V2) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance_squared = offset.magnitude_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = f32::sqrt(distance_squared);
+        Some(LightSample {
+            dir : offset / distance,
+            distance,
+            radiance : self.intensity / distance_squared,
+            pdf : 1.0,
+            delta : true,
+        })
+    }
+
+    fn bounds(&self) -> LightBounds {
+        LightBounds::point(self.position, self.intensity)
+    }
+}
+
+// Light from infinitely far away, like the sun. direction is where the light travels and radiance
+// the irradiance it gives a surface facing it. With an angular radius the light comes from a disk
+// of that size in the sky, which softens shadows, and parallel without one.
+pub struct DirectionalLight {
+    pub direction : V3,
+    pub radiance : V3,
+    pub angular_radius : f32, // radians
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point : V3, u : V2) -> Option<LightSample> {
+        let towards = -self.direction.normalized();
+        if self.angular_radius <= 0.0 {
+            return Some(LightSample {
+                dir : towards,
+                distance : f32::INFINITY,
+                radiance : self.radiance,
+                pdf : 1.0,
+                delta : true,
+            });
+        }
+        // spread over the disk, the radiance per direction and the density cancel out to the same
+        // irradiance
+        // 1 - cos written so it keeps its precision for small disks like the sun
+        let half_sin = f32::sin(self.angular_radius * 0.5);
+        let cos_max = 1.0 - 2.0 * half_sin * half_sin;
+        let solid_angle = 4.0 * PI * half_sin * half_sin;
+        Some(LightSample {
+            dir : sampling::to_world(sampling::uniform_cone(u, cos_max), towards),
+            distance : f32::INFINITY,
+            radiance : self.radiance / solid_angle,
+            pdf : 1.0 / solid_angle,
+            delta : true,
+        })
+    }
+
+    fn bounds(&self) -> LightBounds {
+        LightBounds::infinite(luminance(self.radiance))
+    }
+}
+
+// Luminance in cd/m^2 that shows up as full white, the exposure that ties photometric lights to
+// the 0-255 radiance scale. A white diffuse wall 2m below a 1000cd fixture comes out at about 80.
+pub const WHITE_LUMINANCE : f32 = 100.0;
+
+// intensity on the 0-255 scale of a light of candela in the given color, 1.0 being white
+pub fn from_candela(candela : f32, color : V3) -> V3 {
+    color * (candela * 255.0 / WHITE_LUMINANCE)
+}
 
-pub struct point_light {
-    point : V3,
-    radius : f32,
+// emission from a point towards point, shared by the lights that are points with a direction
+// dependent intensity. gets the unit direction the light leaves in and returns intensity
+fn sample_point_emitter(position : V3, point : V3, intensity : impl Fn(V3) -> V3) -> Option<LightSample> {
+    let offset = position - point;
+    let distance_squared = offset.magnitude_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    let distance = f32::sqrt(distance_squared);
+    let dir = offset / distance;
+    let radiance = intensity(-dir) / distance_squared;
+    if radiance.max_component() <= 0.0 {
+        return None;
+    }
+    Some(LightSample { dir, distance, radiance, pdf : 1.0, delta : true })
 }
 
-impl RayTraceShape for point_light {
-    fn intersect(&self, ray : Ray) -> Option
+// A point light restricted to a cone around direction, at full intensity inside inner_angle and
+// fading smoothly to nothing at outer_angle. Angles are measured from the axis, in radians.
+pub struct SpotLight {
+    pub position : V3,
+    pub direction : V3,
+    pub candela : f32,
+    pub color : V3,
+    pub inner_angle : f32,
+    pub outer_angle : f32,
 }
 
-pub struct directional_light {
-    direction : V3,
+impl SpotLight {
+    pub fn falloff(&self, dir : V3) -> f32 {
+        let cos_theta = dir.normalized().dot(self.direction.normalized());
+        let (cos_inner, cos_outer) = (f32::cos(self.inner_angle), f32::cos(self.outer_angle));
+        if cos_inner <= cos_outer {
+            return if cos_theta >= cos_outer { 1.0 } else { 0.0 };
+        }
+        let t = f32::clamp((cos_theta - cos_outer) / (cos_inner - cos_outer), 0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
 }
 
+impl Light for SpotLight {
+    fn sample(&self, point : V3, _u : V2) -> Option<LightSample> {
+        let intensity = from_candela(self.candela, self.color);
+        sample_point_emitter(self.position, point, |dir| intensity * self.falloff(dir))
+    }
+
+    fn bounds(&self) -> LightBounds {
+        LightBounds {
+            axis : self.direction.normalized(),
+            cos_theta_o : f32::cos(self.outer_angle),
+            ..LightBounds::point(self.position, from_candela(self.candela, self.color))
+        }
+    }
+}
+
+// A point light shaped by a measured IES profile. The profile's downward axis points along
+// direction, with horizontal angle 0 on the first tangent of the orthonormal basis around it.
+// scale multiplies the file's candela values, e.g. for dimming.
+pub struct IesLight {
+    pub position : V3,
+    pub direction : V3,
+    pub profile : IesProfile,
+    pub color : V3,
+    pub scale : f32,
+}
+
+impl IesLight {
+    // candela the fixture sends out along dir
+    pub fn candela(&self, dir : V3) -> f32 {
+        let axis = self.direction.normalized();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let dir = dir.normalized();
+        let vertical = f32::acos(f32::clamp(dir.dot(axis), -1.0, 1.0)).to_degrees();
+        let horizontal = f32::atan2(dir.dot(bitangent), dir.dot(tangent)).to_degrees();
+        self.profile.candela(vertical, horizontal) * self.scale
+    }
+}
+
+impl Light for IesLight {
+    fn sample(&self, point : V3, _u : V2) -> Option<LightSample> {
+        sample_point_emitter(self.position, point, |dir| from_candela(self.candela(dir), self.color))
+    }
+
+    fn bounds(&self) -> LightBounds {
+        LightBounds::point(self.position, from_candela(self.profile.max_candela() * self.scale, self.color))
+    }
+}
+
+// power heuristic with exponent 2 (Veach 1997), weight of a sample from the strategy with density
+// f when the other strategy could have produced it with density g
+pub fn power_heuristic(f : f32, g : f32) -> f32 {
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 <= 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spot_light_fades_between_its_cones() {
+        let spot = SpotLight {
+            position : V3::new(0.0, 2.0, 0.0),
+            direction : V3::new(0.0, -1.0, 0.0),
+            candela : 1000.0,
+            color : V3::new(1.0, 1.0, 1.0),
+            inner_angle : 0.3,
+            outer_angle : 0.6,
+        };
+        let below = spot.sample(V3::zero(), V2::zero()).unwrap();
+        assert_eq!(below.dir, V3::new(0.0, 1.0, 0.0));
+        assert!(f32::abs(below.radiance.x - 1000.0 * 2.55 / 4.0) < 1e-3);
+        let edge = f32::tan(0.45) * 2.0;
+        let fading = spot.sample(V3::new(edge, 0.0, 0.0), V2::zero()).unwrap();
+        assert!(fading.radiance.x > 0.0 && fading.radiance.x < below.radiance.x);
+        assert!(spot.sample(V3::new(4.0, 0.0, 0.0), V2::zero()).is_none());
+    }
+
+    #[test]
+    fn ies_light_follows_its_profile() {
+        let profile = IesProfile {
+            vertical_angles : vec![0.0, 90.0],
+            horizontal_angles : vec![0.0],
+            candela : vec![vec![500.0, 0.0]],
+        };
+        let light = IesLight {
+            position : V3::new(0.0, 1.0, 0.0),
+            direction : V3::new(0.0, -1.0, 0.0),
+            profile,
+            color : V3::new(1.0, 1.0, 1.0),
+            scale : 2.0,
+        };
+        assert_eq!(light.candela(V3::new(0.0, -1.0, 0.0)), 1000.0);
+        assert!(f32::abs(light.candela(V3::new(1.0, -1.0, 0.0)) - 500.0) < 1e-2);
+        assert!(light.sample(V3::new(0.0, 2.0, 0.0), V2::zero()).is_none());
+    }
+}
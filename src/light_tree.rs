@@ -0,0 +1,378 @@
+use std::f32::consts::PI;
+use crate::vector::V3;
+use crate::adaptive::luminance;
+
+// Where a light is, which way it shines and how strongly, coarse enough to merge many lights
+// into one. The light tree uses these to guess how much a light or group of lights contributes
+// at a point (Conty Estevez and Kulla 2018, "Importance Sampling of Many Lights on the GPU").
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightBounds {
+    pub min : V3,
+    pub max : V3,
+    pub axis : V3, // unit direction in the middle of all the normals or spot axes
+    pub cos_theta_o : f32, // cone around axis holding those directions, -1 for all of them
+    pub cos_theta_e : f32, // how far past a normal light still leaves, 0 for a hemisphere
+    pub power : f32, // emitted luminance integrated over area and directions
+}
+
+impl LightBounds {
+    // a point shining in every direction with the given intensity on the 0-255 scale
+    pub fn point(position : V3, intensity : V3) -> Self {
+        LightBounds {
+            min : position,
+            max : position,
+            axis : V3::new(0.0, 0.0, 1.0),
+            cos_theta_o : -1.0,
+            cos_theta_e : 0.0,
+            power : 4.0 * PI * luminance(intensity),
+        }
+    }
+
+    // a diffuse emitter of the given area inside min and max, whose normals all lie within
+    // cos_theta_o of axis
+    pub fn surface(min : V3, max : V3, axis : V3, cos_theta_o : f32, emission : V3, area : f32) -> Self {
+        LightBounds {
+            min,
+            max,
+            axis : axis.normalized(),
+            cos_theta_o,
+            cos_theta_e : 0.0,
+            power : PI * area * luminance(emission),
+        }
+    }
+
+    // a light at infinity, like the sun or the environment, giving the irradiance to a surface
+    // facing it. the power is scaled as for a surface light, whose importance is about pi times
+    // the irradiance it gives, so the tree can weigh the two against each other
+    pub fn infinite(irradiance : f32) -> Self {
+        Self::unbounded(PI * irradiance)
+    }
+
+    // a light of the given power that could be anywhere and shine any way, like an emitter moving
+    // while the shutter is open. the tree weighs it by power alongside the lights at infinity
+    pub fn unbounded(power : f32) -> Self {
+        LightBounds {
+            min : V3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max : V3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            axis : V3::new(0.0, 0.0, 1.0),
+            cos_theta_o : -1.0,
+            cos_theta_e : 0.0,
+            power,
+        }
+    }
+
+    // true for lights at infinity and unbounded lights
+    pub fn is_infinite(&self) -> bool {
+        !(self.min.x.is_finite() && self.max.x.is_finite())
+    }
+
+    pub fn centroid(&self) -> V3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn union(&self, other : &LightBounds) -> LightBounds {
+        if self.power <= 0.0 {
+            return *other;
+        }
+        if other.power <= 0.0 {
+            return *self;
+        }
+        let (axis, cos_theta_o) = cone_union(self.axis, self.cos_theta_o, other.axis, other.cos_theta_o);
+        LightBounds {
+            min : self.min.min_elem(other.min),
+            max : self.max.max_elem(other.max),
+            axis,
+            cos_theta_o,
+            cos_theta_e : f32::min(self.cos_theta_e, other.cos_theta_e),
+            power : self.power + other.power,
+        }
+    }
+
+    // estimated contribution at point: power over squared distance, reduced by how far point lies
+    // outside the cone the lights shine into. a point inside the box could be lit by anything
+    pub fn importance(&self, point : V3) -> f32 {
+        let centre = self.centroid();
+        let distance_squared = (point - centre).magnitude_squared();
+        let radius = (self.max - centre).magnitude();
+        // don't let lights blow up when the point is close to or inside them
+        let clamped_distance_squared = f32::max(distance_squared, radius);
+
+        let theta_w = if distance_squared > 0.0 {
+            f32::acos(f32::clamp(self.axis.dot((point - centre).normalized()), -1.0, 1.0))
+        }
+        else {
+            0.0
+        };
+        // angle the box covers as seen from point
+        let theta_b = if distance_squared <= radius * radius {
+            PI
+        }
+        else {
+            f32::asin(f32::sqrt(radius * radius / distance_squared))
+        };
+        let theta_o = f32::acos(f32::clamp(self.cos_theta_o, -1.0, 1.0));
+        let cos_theta = f32::cos(f32::max(theta_w - theta_o - theta_b, 0.0));
+        if cos_theta <= self.cos_theta_e {
+            return 0.0;
+        }
+        self.power * cos_theta / clamped_distance_squared
+    }
+}
+
+// smallest cone holding two cones, each given by its unit axis and the cosine of its half angle
+fn cone_union(a : V3, cos_a : f32, b : V3, cos_b : f32) -> (V3, f32) {
+    let theta_a = f32::acos(f32::clamp(cos_a, -1.0, 1.0));
+    let theta_b = f32::acos(f32::clamp(cos_b, -1.0, 1.0));
+    let theta_d = f32::acos(f32::clamp(a.dot(b), -1.0, 1.0));
+    if f32::min(theta_d + theta_b, PI) <= theta_a {
+        return (a, cos_a);
+    }
+    if f32::min(theta_d + theta_a, PI) <= theta_b {
+        return (b, cos_b);
+    }
+    let theta_o = (theta_a + theta_d + theta_b) * 0.5;
+    let rotation_axis = a.cross(b);
+    if theta_o >= PI || rotation_axis.magnitude_squared() <= 1e-12 {
+        return (a, -1.0);
+    }
+    // turn a towards b until the cone touches the far sides of both
+    let k = rotation_axis.normalized();
+    let theta_r = theta_o - theta_a;
+    let axis = a * f32::cos(theta_r) + k.cross(a) * f32::sin(theta_r);
+    (axis.normalized(), f32::cos(theta_o))
+}
+
+enum Node {
+    Leaf { bounds : LightBounds, light : usize },
+    // the left child follows its parent directly, right is the index of the other one
+    Interior { bounds : LightBounds, right : usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Bounding volume hierarchy over lights that picks one with probability roughly proportional to
+// what it contributes at the shading point, walking down from the root and choosing between the
+// two children by importance. Lights at infinity and lights without bounds sit beside the tree
+// and compete with its root by power.
+pub struct LightTree {
+    nodes : Vec<Node>,
+    infinite : Vec<(usize, f32)>, // light and power
+    infinite_power : f32,
+    // per light, the left (0) and right (1) turns from the root to its leaf, first turn lowest
+    trails : Vec<Option<u64>>,
+}
+
+impl LightTree {
+    // lights are identified by their position in bounds
+    pub fn new(bounds : &[LightBounds]) -> Self {
+        let mut tree = LightTree {
+            nodes : Vec::new(),
+            infinite : Vec::new(),
+            infinite_power : 0.0,
+            trails : vec![None; bounds.len()],
+        };
+        let mut bounded = Vec::new();
+        for (light, light_bounds) in bounds.iter().enumerate() {
+            if light_bounds.is_infinite() {
+                let power = f32::max(light_bounds.power, 0.0);
+                tree.infinite.push((light, power));
+                tree.infinite_power += power;
+            }
+            else {
+                bounded.push((light, *light_bounds));
+            }
+        }
+        if !bounded.is_empty() {
+            tree.build(&mut bounded, 0, 0);
+        }
+        tree
+    }
+
+    // splits at the median centroid along the widest axis, which keeps the tree balanced so trails
+    // fit in 64 bits for any realistic number of lights
+    fn build(&mut self, lights : &mut [(usize, LightBounds)], trail : u64, depth : u32) -> LightBounds {
+        if lights.len() == 1 {
+            let (light, bounds) = lights[0];
+            self.nodes.push(Node::Leaf { bounds, light });
+            self.trails[light] = Some(trail);
+            return bounds;
+        }
+        let (mut low, mut high) = (lights[0].1.centroid(), lights[0].1.centroid());
+        for (_, bounds) in lights.iter() {
+            low = low.min_elem(bounds.centroid());
+            high = high.max_elem(bounds.centroid());
+        }
+        let extent = high - low;
+        let coordinate = |v : V3| {
+            if extent.x >= extent.y && extent.x >= extent.z { v.x } else if extent.y >= extent.z { v.y } else { v.z }
+        };
+        lights.sort_by(|a, b| coordinate(a.1.centroid()).total_cmp(&coordinate(b.1.centroid())));
+
+        let node = self.nodes.len();
+        self.nodes.push(Node::Leaf { bounds : lights[0].1, light : 0 });
+        let mid = lights.len() / 2;
+        let left = self.build(&mut lights[..mid], trail, depth + 1);
+        let right = self.nodes.len();
+        let right_bounds = self.build(&mut lights[mid..], trail | 1 << depth, depth + 1);
+        let bounds = left.union(&right_bounds);
+        self.nodes[node] = Node::Interior { bounds, right };
+        bounds
+    }
+
+    // probability of picking from the lights beside the tree at point, the tree gets the rest
+    fn infinite_probability(&self, point : V3) -> f32 {
+        let tree_importance = self.nodes.first().map_or(0.0, |root| root.bounds().importance(point));
+        let total = self.infinite_power + tree_importance;
+        if total > 0.0 { self.infinite_power / total } else { 0.0 }
+    }
+
+    // picks a light for shading point with u in [0, 1), returning it with the probability of
+    // having picked it. None when nothing could light the point
+    pub fn sample(&self, point : V3, u : f32) -> Option<(usize, f32)> {
+        let p_infinite = self.infinite_probability(point);
+        if u < p_infinite {
+            let target = u / p_infinite * self.infinite_power;
+            let mut total = 0.0;
+            for &(light, power) in &self.infinite {
+                total += power;
+                if target < total || total >= self.infinite_power {
+                    return Some((light, p_infinite * power / self.infinite_power));
+                }
+            }
+        }
+        let p_tree = 1.0 - p_infinite;
+        if self.nodes.is_empty() || p_tree <= 0.0 {
+            return None;
+        }
+        let mut u = f32::clamp((u - p_infinite) / p_tree, 0.0, 1.0 - f32::EPSILON);
+        let mut pmf = p_tree;
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                Node::Leaf { bounds, light } => {
+                    return if bounds.importance(point) > 0.0 { Some((*light, pmf)) } else { None };
+                }
+                Node::Interior { right, .. } => {
+                    let left_importance = self.nodes[node + 1].bounds().importance(point);
+                    let right_importance = self.nodes[*right].bounds().importance(point);
+                    let total = left_importance + right_importance;
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let p_left = left_importance / total;
+                    if u < p_left {
+                        u /= p_left;
+                        pmf *= p_left;
+                        node += 1;
+                    }
+                    else {
+                        u = f32::min((u - p_left) / (1.0 - p_left), 1.0 - f32::EPSILON);
+                        pmf *= 1.0 - p_left;
+                        node = *right;
+                    }
+                }
+            }
+        }
+    }
+
+    // probability that sample picks light for shading point
+    pub fn pmf(&self, point : V3, light : usize) -> f32 {
+        let p_infinite = self.infinite_probability(point);
+        if let Some(&(_, power)) = self.infinite.iter().find(|(infinite, _)| *infinite == light) {
+            return if self.infinite_power > 0.0 { p_infinite * power / self.infinite_power } else { 0.0 };
+        }
+        let Some(Some(mut trail)) = self.trails.get(light).copied() else { return 0.0 };
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                Node::Leaf { bounds, .. } => {
+                    return if bounds.importance(point) > 0.0 { pmf } else { 0.0 };
+                }
+                Node::Interior { right, .. } => {
+                    let left_importance = self.nodes[node + 1].bounds().importance(point);
+                    let right_importance = self.nodes[*right].bounds().importance(point);
+                    let total = left_importance + right_importance;
+                    if total <= 0.0 {
+                        return 0.0;
+                    }
+                    if trail & 1 == 0 {
+                        pmf *= left_importance / total;
+                        node += 1;
+                    }
+                    else {
+                        pmf *= right_importance / total;
+                        node = *right;
+                    }
+                    trail >>= 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_of_lights() -> Vec<LightBounds> {
+        let mut bounds : Vec<LightBounds> = (0..7)
+            .map(|i| LightBounds::point(V3::new(i as f32 * 3.0, 0.0, 0.0), V3::new(255.0, 255.0, 255.0)))
+            .collect();
+        bounds.push(LightBounds::unbounded(4.0 * PI * 255.0));
+        bounds
+    }
+
+    #[test]
+    fn sampling_favours_nearby_lights_and_matches_the_pmf() {
+        let tree = LightTree::new(&row_of_lights());
+        let point = V3::new(0.0, 0.5, 0.0);
+        let mut counts = [0usize; 8];
+        let n = 20000;
+        for i in 0..n {
+            let (light, pmf) = tree.sample(point, (i as f32 + 0.5) / n as f32).unwrap();
+            assert!((pmf - tree.pmf(point, light)).abs() < 1e-5);
+            counts[light] += 1;
+        }
+        assert!(counts[0] > counts[6] * 10);
+        assert!((counts[7] as f32 / n as f32 - tree.pmf(point, 7)).abs() < 1e-3);
+        let total : f32 = (0..8).map(|light| tree.pmf(point, light)).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lights_facing_away_get_no_samples() {
+        let facing_up = LightBounds::surface(V3::new(-1.0, 0.0, -1.0), V3::new(1.0, 0.0, 1.0), V3::new(0.0, 1.0, 0.0), 1.0, V3::new(255.0, 255.0, 255.0), 4.0);
+        let elsewhere = LightBounds::point(V3::new(10.0, 0.0, 0.0), V3::new(255.0, 255.0, 255.0));
+        let tree = LightTree::new(&[facing_up, elsewhere]);
+        let below = V3::new(0.0, -3.0, 0.0);
+        assert_eq!(tree.pmf(below, 0), 0.0);
+        assert_eq!(tree.pmf(below, 1), 1.0);
+        assert!(tree.pmf(V3::new(0.0, 3.0, 0.0), 0) > 0.5);
+    }
+
+    #[test]
+    fn lights_beside_the_tree_get_a_share_by_power() {
+        let lamp = LightBounds::point(V3::new(0.0, 2.0, 0.0), V3::new(255.0, 255.0, 255.0));
+        let point = V3::zero();
+        for beside in [LightBounds::infinite as fn(f32) -> LightBounds, LightBounds::unbounded] {
+            let dim = LightTree::new(&[lamp, beside(1.0)]);
+            let bright = LightTree::new(&[lamp, beside(1e5)]);
+            assert!(dim.pmf(point, 1) < 0.01);
+            assert!(bright.pmf(point, 1) > 0.9);
+            for tree in [dim, bright] {
+                assert!((tree.pmf(point, 0) + tree.pmf(point, 1) - 1.0).abs() < 1e-5);
+                for u in [0.001, 0.5, 0.999] {
+                    let (light, pmf) = tree.sample(point, u).unwrap();
+                    assert!((pmf - tree.pmf(point, light)).abs() < 1e-6);
+                }
+            }
+        }
+    }
+}
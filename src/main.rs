@@ -1,6 +1,7 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
+use std::sync::Arc;
 use pixels::{Error, Pixels, SurfaceTexture};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
@@ -14,19 +15,46 @@ mod ray;
 mod plane;
 mod shape;
 mod scene;
+mod camera;
+mod stereo;
 mod sphere; 
 mod material;
 mod matrix;
+mod sampling;
+mod sampler;
+mod filter;
+mod film;
+mod adaptive;
+mod integrator;
+mod light;
+mod light_tree;
+mod medium;
+mod grid_medium;
+mod ies;
+mod environment;
+mod sky;
+mod spectrum;
+mod quaternion;
+mod transform;
+mod instance;
+mod quad;
+mod disk;
+mod mesh;
+mod cuboid;
 
-use scene::Scene;
-use scene::Camera;
-use ray::Ray;
-use vector::V3;
-use plane::Plane;
-use shape::RayTraceShape;
+use camera::{Camera, Projection, FisheyeMapping};
+use vector::{V2, V3};
 use color::Col3;
 use material::Material;
+use stereo::{StereoRig, StereoMode, StereoLayout};
 use matrix::Matrix3;
+use quaternion::Quaternion;
+use sampler::SamplerKind;
+use filter::Filter;
+use adaptive::AdaptiveSampling;
+use integrator::IntegratorKind;
+use transform::{Transform, AnimatedTransform, Keyframe};
+use instance::Instance;
 const WIDTH: u32 = 512;
 const HEIGHT: u32 = 512;
 const BOX_SIZE: i16 = 64;
@@ -40,91 +68,275 @@ struct World {
 }
 
 fn main() -> Result<(), Error> {
-    let mut scene = scene::Scene {
-        geometry : vec![
+    let mut scene = scene::Scene::new(
+        vec![
             Box::new(plane::Plane {
                 origin : V3::new(0.0, 0.0, 6.5),
                 base_one : V3::new(1.0, 0.0, 0.0),
                 base_two : V3::new(0.0, 1.0, 0.0),
-                material : Material::new(0.9, 0.95, Col3::new(100, 255, 120), 0.95)
+                material : Material::new(0.95, Col3::new(100, 255, 120))
             }),
             Box::new(plane::Plane {
                 origin : V3::new(0.0, 0.0, -6.5),
                 base_one : V3::new(1.0, 0.0, 0.0),
                 base_two : V3::new(0.0, 1.0, 0.0),
-                material : Material::new(0.9, 0.95, Col3::new(100, 255, 120), 0.95)
+                material : Material::new(0.95, Col3::new(100, 255, 120))
             }), 
 
             Box::new(plane::Plane {
                 origin : V3::new(3.5, 0.0, 0.0),
                 base_one : V3::new(0.0, 0.0, 1.0),
                 base_two : V3::new(0.0, 1.0, 0.0),
-                material : Material::new(0.9, 0.95, Col3::new(100, 120, 255), 0.8)
+                material : Material::new(0.95, Col3::new(100, 120, 255))
             }),
 
             Box::new(plane::Plane {
                 origin : V3::new(-3.5, 0.0, 0.0),
                 base_one : V3::new(0.0, 0.0, 1.0),
                 base_two : V3::new(0.0, 1.0, 0.0),
-                material : Material::new(0.9, 0.4, Col3::new(255, 120, 100), 0.9)
+                material : Material::new(0.4, Col3::new(255, 120, 100))
             }),
             
             Box::new(plane::Plane {
                 origin : V3::new(0.0, -2.0, 0.0),
                 base_one : V3::new(1.0, 0.0, 0.0),
                 base_two : V3::new(0.0, 0.0, 1.0),
-                material : Material::new(0.9, 0.7, Col3::new(255, 255, 255), 0.87)
+                material : Material::new(0.7, Col3::new(255, 255, 255))
             }),
             Box::new(plane::Plane {
                 origin : V3::new(0.0, 3.0, 0.0),
                 base_one : V3::new(1.0, 0.0, 0.0),
                 base_two : V3::new(0.0, 0.0, 1.0),
-                material : Material::new(0.9, 0.7, Col3::new(0, 255, 255), 0.87)
+                material : Material::new(0.7, Col3::new(0, 255, 255))
             }),
             Box::new(sphere::Sphere {
                 origin : V3::new(0.0, 1.0, 2.0),
                 radius : 1.0,
-                material : Material::new(0.5, 0.9, Col3::new(255, 0, 0), 0.9)
+                material : Material::new(0.9, Col3::new(255, 0, 0))
 
             }),
             Box::new(sphere::Sphere {
                 origin : V3::new(1.2, 0.5, 1.0),
                 radius : 0.5,
-                material : Material::new(0.5, 0.9, Col3::new(255, 255, 240), 1.0)
+                material : Material::new(0.9, Col3::new(255, 255, 240))
 
             }),
+            // ceiling panel that lights the room without showing up in the picture
+            Box::new(quad::Quad {
+                origin : V3::new(-1.0, 2.95, 1.0),
+                base_one : V3::new(2.0, 0.0, 0.0),
+                base_two : V3::new(0.0, 0.0, 2.0),
+                material : Material::light(Col3::new(255, 250, 240), 2.0).hidden_from_camera()
+            }),
+            Box::new(sphere::Sphere {
+                origin : V3::new(0.0, 2.4, 3.5),
+                radius : 0.4,
+                material : Material::blackbody(2700.0, 6.0)
+            }),
             Box::new(sphere::Sphere {
                 origin : V3::new(2.0, 1.0, 3.0),
                 radius : 1.0,
-                material : Material::new(0.5, 0.7, Col3::new(120, 200, 150), 0.7)
+                material : Material::new(0.7, Col3::new(120, 200, 150))
 
             })
         ],
-        camera : Camera {
+        vec![
+            Box::new(light::PointLight {
+                position : V3::new(-2.0, 1.5, -1.0),
+                intensity : spectrum::blackbody_normalized(3200.0) * (255.0 * 3.0),
+            }),
+        ],
+        Camera {
             location : V3::new(1.8, 1.5, 0.0),
-            rotation_y : 0.0,
-            rotation_x : 3.141025 * 0.25,
-            viewport_anchor : V3::new(-1.0, 0.0, 0.25), // relative to the camera. The botleft corner of the viewport in camera
-                                  // space
+            orientation : Quaternion::from_axis_angle(V3::new(1.0, 0.0, 0.0), 3.141025 * 0.25),
+            projection : Projection::Perspective,
+            fov_y : 3.141025 * 0.5,
+            lens_shift : V2::zero(),
+            aperture_radius : 0.0,
+            focus_distance : 3.0,
+            aperture_blades : 0,
+            blade_rotation : 0.0,
+            shutter_open : 0.0,
+            shutter_close : 1.0,
             size_x : 512,
             size_y : 512,
             rays_per_pixel : 2,
+            sampler : SamplerKind::Sobol,
+            seed : 0,
+            filter : Filter::mitchell(),
+            adaptive : None,
+            integrator : IntegratorKind::Path,
             bounce_depth : 2,
+            roulette_depth : 1,
             max_steps : 1,
             step_len : 1000.0
         }
-    };
-    let mut img = scene.render();
+    );
+    // --integrator path|whitted|ao|direct picks the rendering algorithm, I cycles it in the viewer
+    let args : Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--integrator") {
+        match args.get(pos + 1).and_then(|name| IntegratorKind::from_name(name)) {
+            Some(kind) => scene.camera.integrator = kind,
+            None => eprintln!("unknown integrator, expected one of path, whitted, ao, direct"),
+        }
+    }
+    // --projection perspective|orthographic|equirect|fisheye|fisheye-equidistant|cubemap-strip|cubemap-cross,
+    // panoramas get an image size that fits their layout
+    if let Some(pos) = args.iter().position(|arg| arg == "--projection") {
+        match args.get(pos + 1).and_then(|name| Projection::from_name(name)) {
+            Some(projection) => {
+                scene.camera.projection = projection;
+                if let Some((size_x, size_y)) = projection.panorama_size(128) {
+                    scene.camera.size_x = size_x;
+                    scene.camera.size_y = size_y;
+                }
+            }
+            None => eprintln!("unknown projection, expected one of perspective, orthographic, equirect, fisheye, fisheye-equidistant, cubemap-strip, cubemap-cross"),
+        }
+    }
+    // --stereo side-by-side|over-under|anaglyph [off-axis|toe-in|omni] renders both eyes of a stereo rig
+    let mut stereo : Option<StereoRig> = None;
+    if let Some(pos) = args.iter().position(|arg| arg == "--stereo") {
+        let layout = args.get(pos + 1).and_then(|name| StereoLayout::from_name(name));
+        let mode = args.get(pos + 2).and_then(|name| StereoMode::from_name(name)).unwrap_or(StereoMode::OffAxis);
+        match layout {
+            Some(layout) => stereo = Some(StereoRig { ipd : 0.064, convergence : 3.0, mode, layout }),
+            None => eprintln!("unknown stereo layout, expected one of side-by-side, over-under, anaglyph"),
+        }
+    }
+    // --f-stop n opens the lens like a full frame camera at that f-number, with the scene in metres
+    if let Some(pos) = args.iter().position(|arg| arg == "--f-stop") {
+        match args.get(pos + 1).and_then(|f_stop| f_stop.parse::<f32>().ok()) {
+            Some(f_stop) => scene.camera.set_f_stop(f_stop, 0.024),
+            None => eprintln!("--f-stop needs an f-number"),
+        }
+    }
+    // --environment file.hdr|file.exr lights the scene with an equirectangular image
+    if let Some(pos) = args.iter().position(|arg| arg == "--environment") {
+        match args.get(pos + 1).map(environment::EnvironmentMap::load) {
+            Some(Ok(map)) => scene.set_environment(Some(map)),
+            Some(Err(err)) => eprintln!("could not load environment map: {err}"),
+            None => eprintln!("--environment needs a path"),
+        }
+    }
+    // --sky elevation azimuth turbidity, angles in degrees, lights the scene with daylight and a sun.
+    // the sky is an environment map itself, so it can't be combined with --environment
+    if args.iter().any(|arg| arg == "--sky") && args.iter().any(|arg| arg == "--environment") {
+        eprintln!("--sky and --environment both set the environment, ignoring --sky");
+    }
+    else if let Some(pos) = args.iter().position(|arg| arg == "--sky") {
+        let values : Vec<f32> = args[pos + 1..].iter().map_while(|arg| arg.parse().ok()).take(3).collect();
+        match values[..] {
+            [elevation, azimuth, turbidity] => {
+                let sky = sky::Sky::new(elevation.to_radians(), azimuth.to_radians(), turbidity);
+                scene.set_environment(Some(sky.environment(256, 128)));
+                scene.add_light(Box::new(sky.sun()));
+            }
+            _ => eprintln!("--sky needs a sun elevation, sun azimuth and turbidity"),
+        }
+    }
+    // --tint kelvin colors the environment like a blackbody of that temperature
+    if let Some(pos) = args.iter().position(|arg| arg == "--tint") {
+        match (args.get(pos + 1).and_then(|arg| arg.parse::<f32>().ok()), scene.environment_mut()) {
+            (Some(kelvin), Some(environment)) => environment.set_tint(spectrum::blackbody_normalized(kelvin)),
+            (None, _) => eprintln!("--tint needs a temperature in kelvin"),
+            (_, None) => eprintln!("--tint needs --environment or --sky"),
+        }
+    }
+    // --ies file.ies hangs a fixture with that photometric profile from the ceiling
+    if let Some(pos) = args.iter().position(|arg| arg == "--ies") {
+        match args.get(pos + 1).map(ies::IesProfile::load) {
+            Some(Ok(profile)) => scene.add_light(Box::new(light::IesLight {
+                position : V3::new(1.0, 2.9, 2.0),
+                direction : V3::new(0.0, -1.0, 0.0),
+                profile,
+                color : V3::new(1.0, 0.95, 0.9),
+                scale : 1.0,
+            })),
+            Some(Err(err)) => eprintln!("could not load ies profile: {err}"),
+            None => eprintln!("--ies needs a path"),
+        }
+    }
+    // --showcase adds a disk lamp, a spot light and two instances of one pyramid mesh, the second
+    // turning while the shutter is open
+    if args.iter().any(|arg| arg == "--showcase") {
+        scene.add_shape(Box::new(disk::Disk {
+            origin : V3::new(-2.0, 2.95, 4.0),
+            normal : V3::new(0.0, -1.0, 0.0),
+            radius : 0.4,
+            material : Material::blackbody(4000.0, 3.0),
+        }));
+        scene.add_light(Box::new(light::SpotLight {
+            position : V3::new(2.5, 2.9, 4.5),
+            direction : V3::new(-0.5, -1.9, -1.5),
+            candela : 150.0,
+            color : spectrum::blackbody_normalized(3000.0),
+            inner_angle : 0.3,
+            outer_angle : 0.5,
+        }));
+        let pyramid : Arc<dyn shape::RayTraceShape> = Arc::new(mesh::Mesh::new(
+            vec![V3::new(-1.0, 0.0, -1.0), V3::new(1.0, 0.0, -1.0), V3::new(1.0, 0.0, 1.0), V3::new(-1.0, 0.0, 1.0), V3::new(0.0, 1.5, 0.0)],
+            vec![[1, 0, 4], [2, 1, 4], [3, 2, 4], [0, 3, 4], [0, 1, 2], [0, 2, 3]],
+            Material::new(0.2, Col3::new(230, 200, 120)),
+        ));
+        let placement = Transform::scaling(V3::new(0.6, 1.0, 0.6))
+            .then(&Transform::rotation_y(0.4))
+            .then(&Transform::translation(V3::new(-1.8, -2.0, 3.0)));
+        scene.add_shape(Box::new(Instance::new(pyramid.clone(), placement)));
+        let up = V3::new(0.0, 1.0, 0.0);
+        let (position, scale) = (V3::new(-1.0, -2.0, 4.5), V3::new(0.5, 0.5, 0.5));
+        scene.add_shape(Box::new(Instance::animated(pyramid, AnimatedTransform::new(vec![
+            Keyframe::new(0.0, position, Quaternion::identity(), scale),
+            Keyframe::new(1.0, position, Quaternion::from_axis_angle(up, 0.6), scale),
+        ]))));
+    }
+    // --fog density fills the room with fog that scatters the light it takes out of rays
+    if let Some(pos) = args.iter().position(|arg| arg == "--fog") {
+        match args.get(pos + 1).and_then(|density| density.parse::<f32>().ok()) {
+            Some(density) => scene.medium = Some(Box::new(medium::HomogeneousMedium::fog(density, 0.3))),
+            None => eprintln!("--fog needs a density"),
+        }
+    }
+    // --cloud [file.vox] floats a cloud in the middle of the room, from a voxel file or from noise
+    if let Some(pos) = args.iter().position(|arg| arg == "--cloud") {
+        let grid = match args.get(pos + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(path) => grid_medium::VoxelGrid::load(path),
+            None => Ok(grid_medium::VoxelGrid::cloud(64, 0)),
+        };
+        match grid {
+            Ok(grid) => {
+                let (min, max) = (V3::new(-1.5, -0.5, 0.5), V3::new(1.5, 2.0, 4.5));
+                let mut medium = grid_medium::GridMedium::new(grid.clone(), min, max, V3::new(0.2, 0.2, 0.2), V3::new(3.0, 3.0, 3.0), 0.6);
+                // --fire makes the cloud glow where it is dense, like flames
+                if args.iter().any(|arg| arg == "--fire") {
+                    medium = medium.with_emission(grid, spectrum::blackbody_normalized(1800.0) * (255.0 * 4.0));
+                }
+                scene.volumes.push(medium::Volume {
+                    boundary : Box::new(cuboid::Cuboid { min, max, material : Material::new(0.0, Col3::black()) }),
+                    medium : Box::new(medium),
+                });
+            }
+            Err(err) => eprintln!("could not load voxel grid: {err}"),
+        }
+    }
+    scene.build_light_tree();
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
 
     let mut velocity : V3 = V3::zero();
     let mut rot_vel : f32 = 0.0;
+    let mut roll_vel : f32 = 0.0;
     let mut raycnt : u16 = 1;
+    // size of the image on screen, both eyes side by side or stacked for some stereo layouts
+    let frame_size = |camera : &Camera, stereo : Option<StereoRig>| match stereo {
+        Some(rig) => rig.output_size(camera),
+        None => (camera.size_x as u32, camera.size_y as u32),
+    };
+    let mut buffer_size = frame_size(&scene.camera, stereo);
     let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
+        let size = LogicalSize::new(buffer_size.0 as f64, buffer_size.1 as f64);
         WindowBuilder::new()
-            .with_title("Hello Pixels")
+            .with_title(window_title(&scene.camera))
             .with_inner_size(size)
             .with_min_inner_size(size)
             .build(&event_loop)
@@ -134,7 +346,7 @@ fn main() -> Result<(), Error> {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
+        Pixels::new(buffer_size.0, buffer_size.1, surface_texture)?
     };
     
     
@@ -143,11 +355,18 @@ fn main() -> Result<(), Error> {
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            img = scene.render();
+            let img = match stereo {
+                Some(rig) => rig.render(&scene),
+                None => scene.render(),
+            };
+            let size = frame_size(&scene.camera, stereo);
+            if size != buffer_size {
+                if let Err(err) = pixels.resize_buffer(size.0, size.1) {
+                    panic!("pixels.resize_buffer() failed: {err}");
+                }
+                buffer_size = size;
+            }
             for (i, pixel) in pixels.get_frame_mut().chunks_exact_mut(4).enumerate() {
-                let x = (i % WIDTH as usize) as i16;
-                let y = (i / WIDTH as usize) as i16;
-
                 let a = img[i];
                 let rgba = [a.r, a.g, a.b, 0xff];
 
@@ -155,18 +374,20 @@ fn main() -> Result<(), Error> {
             }
             if let Err(err) = pixels.render() {
                 panic!("pixels.render() failed: {err}");
-                *control_flow = ControlFlow::Exit;
-                return;
             }
         }
 
         // Handle input events
         if input.update(&event) {
-            scene.camera.location = scene.camera.location + Matrix3::rotation_y(scene.camera.rotation_y).transform_vec3(velocity);
+            // movement and turning happen in the horizontal plane, independent of pitch and roll
+            let (yaw, pitch, roll) = scene.camera.euler();
+            scene.camera.location += Matrix3::rotation_y(yaw).transform_vec3(velocity);
             scene.camera.rays_per_pixel = raycnt;
-            scene.camera.rotation_y = scene.camera.rotation_y + rot_vel;
+            scene.camera.set_euler(yaw + rot_vel, pitch, roll);
+            scene.camera.roll(roll_vel);
             velocity = 0.3 * velocity;
-            rot_vel = 0.5 * rot_vel;
+            rot_vel *= 0.5;
+            roll_vel *= 0.5;
             let accel = 0.1;
             // Close events
             if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
@@ -174,41 +395,95 @@ fn main() -> Result<(), Error> {
                 return;
             }
             if input.key_pressed(VirtualKeyCode::R) {
-                scene.camera.bounce_depth = scene.camera.bounce_depth + 1;
+                scene.camera.bounce_depth += 1;
             }
             if input.key_pressed(VirtualKeyCode::E) {
-                rot_vel = rot_vel + 3.141502 * 0.125;
+                rot_vel += 3.141502 * 0.125;
             }
             if input.key_pressed(VirtualKeyCode::Q) {
-                rot_vel = rot_vel - 3.141502 * 0.125;
+                rot_vel -= 3.141502 * 0.125;
 
             }
+            if input.key_pressed(VirtualKeyCode::Z) {
+                roll_vel += 3.141502 * 0.0625;
+            }
+            if input.key_pressed(VirtualKeyCode::X) {
+                roll_vel -= 3.141502 * 0.0625;
+            }
 
             if input.key_pressed(VirtualKeyCode::W) {
-                velocity = velocity + V3::new(0.0, 0.0, accel);
+                velocity += V3::new(0.0, 0.0, accel);
             }
             if input.key_pressed(VirtualKeyCode::A) {
-                velocity = velocity + V3::new(-accel, 0.0, 0.0);
+                velocity += V3::new(-accel, 0.0, 0.0);
             }
             if input.key_pressed(VirtualKeyCode::S) {
-                velocity = velocity + V3::new(0.0, 0.0, -accel);
+                velocity += V3::new(0.0, 0.0, -accel);
             }
             if input.key_pressed(VirtualKeyCode::D) {
-                velocity = velocity + V3::new(accel, 0.0, 0.0);
+                velocity += V3::new(accel, 0.0, 0.0);
             }
             if input.key_pressed(VirtualKeyCode::Space) {
-                velocity = velocity + V3::new(0.0, accel, 0.0);
+                velocity += V3::new(0.0, accel, 0.0);
             }
             if input.key_pressed(VirtualKeyCode::C) {
-                velocity = velocity + V3::new(0.0, -accel, 0.0);
+                velocity += V3::new(0.0, -accel, 0.0);
             }
             if input.key_pressed(VirtualKeyCode::O) {
-                raycnt = raycnt + 20;
+                raycnt += 20;
             }
             if input.key_pressed(VirtualKeyCode::P) {
                 raycnt = 2;
                 scene.camera.bounce_depth = 1;
             }
+            if input.key_pressed(VirtualKeyCode::G) {
+                // toggle between a pinhole and a wide open lens
+                scene.camera.aperture_radius = if scene.camera.aperture_radius > 0.0 { 0.0 } else { 0.1 };
+            }
+            if input.key_pressed(VirtualKeyCode::N) {
+                // red-cyan stereo keeps the window size, so it can be previewed directly
+                stereo = match stereo {
+                    Some(_) => None,
+                    None => Some(StereoRig { ipd : 0.064, convergence : 3.0, mode : StereoMode::OffAxis, layout : StereoLayout::Anaglyph }),
+                };
+            }
+            if input.key_pressed(VirtualKeyCode::V) {
+                // cycle through the projections that fit the square viewer window
+                scene.camera.projection = match scene.camera.projection {
+                    Projection::Perspective => Projection::Orthographic { view_width : 6.0 },
+                    Projection::Orthographic { .. } => Projection::Fisheye { fov : 3.141025, mapping : FisheyeMapping::Equisolid },
+                    _ => Projection::Perspective,
+                };
+            }
+            if input.key_pressed(VirtualKeyCode::M) {
+                scene.camera.sampler = scene.camera.sampler.next();
+                window.set_title(&window_title(&scene.camera));
+            }
+            if input.key_pressed(VirtualKeyCode::B) {
+                scene.camera.filter = scene.camera.filter.next();
+                window.set_title(&window_title(&scene.camera));
+            }
+            if input.key_pressed(VirtualKeyCode::T) {
+                scene.camera.adaptive = match scene.camera.adaptive {
+                    None => Some(AdaptiveSampling::new(64, 0.05)),
+                    Some(_) => None,
+                };
+            }
+            if input.key_pressed(VirtualKeyCode::Y) {
+                if let Some(adaptive) = scene.camera.adaptive.as_mut() {
+                    adaptive.show_sample_counts = !adaptive.show_sample_counts;
+                }
+            }
+            if input.key_pressed(VirtualKeyCode::I) {
+                scene.camera.integrator = scene.camera.integrator.next();
+                window.set_title(&window_title(&scene.camera));
+            }
+            if input.key_pressed(VirtualKeyCode::F) {
+                let (cx, cy) = (scene.camera.size_x / 2, scene.camera.size_y / 2);
+                if scene.autofocus(cx, cy).is_some() {
+                    window.set_title(&window_title(&scene.camera));
+                }
+            }
 
             println!("velocity {:?}", velocity);
             println!("position {:?}", scene.camera.location);
@@ -216,8 +491,6 @@ fn main() -> Result<(), Error> {
             if let Some(size) = input.window_resized() {
                 if let Err(err) = pixels.resize_surface(size.width, size.height) {
                     panic!("pixels.resize_surface() failed: {err}");
-                    *control_flow = ControlFlow::Exit;
-                    return;
                 }
             }
 
@@ -229,6 +502,11 @@ fn main() -> Result<(), Error> {
     
 }
 
+// the settings the keys change, shown in the title bar
+fn window_title(camera : &Camera) -> String {
+    format!("Hello Pixels - focus {:.2}, {:?} sampler, {} filter, {:?} integrator", camera.focus_distance, camera.sampler, camera.filter.kind.name(), camera.integrator)
+}
+
 impl World {
     /// Create a new `World` instance that can draw a moving box.
     fn new() -> Self {
@@ -257,6 +535,7 @@ impl World {
     /// Draw the `World` state to the frame buffer.
     ///
     /// Assumes the default texture format: `wgpu::TextureFormat::Rgba8UnormSrgb`
+    #[allow(dead_code)]
     fn draw(&self, frame: &mut [u8]) {
         for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
             let x = (i % WIDTH as usize) as i16;
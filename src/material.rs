@@ -1,54 +1,117 @@
+use std::f32::consts::FRAC_1_PI;
 use crate::vector::V3;
 use crate::color::Col3;
-use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sampling;
+use crate::spectrum;
 
 #[derive(Clone, Copy)]
 pub struct Material {
-    pub albedo : f32,
-    pub smoothness : f32,
+    pub smoothness : f32, // share of light reflected like a mirror, the rest scatters diffusely
     pub color : Col3,
-    pub brightness : f32,
+    pub emission : V3, // radiance given off the front of the surface, on the same 0-255 scale as colors
+    pub visible_to_camera : bool, // false lets camera rays pass through, e.g. to light a shot with an unseen lamp
+}
+
+// A direction picked by Material::sample_bsdf. weight is the bsdf times the cosine over the pdf,
+// i.e. what the path throughput gets multiplied by when following it.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    pub dir : V3,
+    pub weight : V3,
+    pub pdf : f32,
+    pub specular : bool, // mirror bounces have a delta pdf, light sampling can never hit them
 }
 
 impl Material {
-    pub fn new(albedo : f32, smoothness : f32, color : Col3, brightness : f32) -> Self {
+    pub fn new(smoothness : f32, color : Col3) -> Self {
         Material {
-            albedo,
             smoothness,
             color,
-            brightness,
+            emission : V3::zero(),
+            visible_to_camera : true,
+        }
+    }
+
+    // a black surface that only gives off light, strength scales the color
+    pub fn light(color : Col3, strength : f32) -> Self {
+        Material {
+            emission : color.to_v3() * strength,
+            ..Material::new(0.0, Col3::black())
         }
     }
-    pub fn shade_ray_reflection(&self, ray : &mut Ray, surface_normal : V3) {
-        self.displace_ray(ray, surface_normal);
-        self.shade_color(ray);
-        self.shade_lighting(ray);
+
+    // a light glowing with the color of a blackbody at kelvin, as bright as a white light of the
+    // same strength
+    pub fn blackbody(kelvin : f32, strength : f32) -> Self {
+        Material {
+            emission : spectrum::blackbody_normalized(kelvin) * (255.0 * strength),
+            ..Material::new(0.0, Col3::black())
+        }
     }
 
-    fn shade_lighting(&self, ray : &mut Ray) {
-        ray.gamma = self.brightness;// * ray.gamma;
-        if self.brightness > 1.9 {
-            ray.bounces_remaining = 1;
+    pub fn hidden_from_camera(self) -> Self {
+        Material {
+            visible_to_camera : false,
+            ..self
         }
     }
 
-    fn shade_color(&self, ray : &mut Ray) {
-        let reflected = ray.color * self.color;
-        let intrinsic = self.color;
+    pub fn is_emissive(&self) -> bool {
+        self.emission.max_component() > 0.0
+    }
+
+    // share of incoming light reflected per channel
+    pub fn reflectance(&self) -> V3 {
+        self.color.to_v3() / 255.0
+    }
+
+    // Light scattered from wi towards wo, times the cosine at the surface. Only the diffuse lobe
+    // shows up here, the mirror lobe is a delta that only sample_bsdf can pick. All directions
+    // point away from the surface and normal faces the viewer.
+    pub fn eval_bsdf(&self, wo : V3, wi : V3, normal : V3) -> V3 {
+        let cos_i = wi.dot(normal);
+        if cos_i <= 0.0 || wo.dot(normal) <= 0.0 {
+            return V3::zero();
+        }
+        self.reflectance() * ((1.0 - self.smoothness) * FRAC_1_PI * cos_i)
+    }
 
-        ray.color = reflected; //* (1.0 - self.albedo) + intrinsic * self.albedo;
+    // solid angle density of sample_bsdf picking wi through the diffuse lobe
+    pub fn pdf(&self, wo : V3, wi : V3, normal : V3) -> f32 {
+        let cos_i = wi.dot(normal);
+        if cos_i <= 0.0 || wo.dot(normal) <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - self.smoothness) * FRAC_1_PI * cos_i
     }
 
-    fn displace_ray(&self, ray : &mut Ray, surface_normal : V3) {
-        // TODO: replace with real pi
-        let pi = 3.141052;
-        
-        // plane in parametric form, as derived from its normal vector 
-        let basis_t = V3::new(surface_normal.y, 1.0, 0.0).normalized();
-        let basis_s = V3::new(surface_normal.z, 0.0, 1.0).normalized();
-        
-        // combine all the bases + form a disk in 3d space
-        let rand_disk = (basis_t * f32::cos(fastrand::f32() * pi) + basis_s * f32::cos(fastrand::f32() * pi));
-        ray.dir = (ray.dir.normalized() * self.smoothness + rand_disk * (1.0-self.smoothness)).normalized();
+    // picks the mirror lobe with probability smoothness, otherwise a cosine weighted diffuse
+    // direction. None when the surface doesn't reflect towards wo at all
+    pub fn sample_bsdf(&self, wo : V3, normal : V3, sampler : &mut dyn Sampler) -> Option<BsdfSample> {
+        let lobe = sampler.get_1d();
+        let u = sampler.get_2d();
+        if wo.dot(normal) <= 0.0 {
+            return None;
+        }
+        if lobe < self.smoothness {
+            return Some(BsdfSample {
+                dir : (-wo).reflect(normal),
+                weight : self.reflectance(),
+                pdf : 0.0,
+                specular : true,
+            });
+        }
+        let dir = sampling::to_world(sampling::cosine_hemisphere(u), normal).normalized();
+        let pdf = self.pdf(wo, dir, normal);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            dir,
+            weight : self.eval_bsdf(wo, dir, normal) / pdf,
+            pdf,
+            specular : false,
+        })
     }
 }
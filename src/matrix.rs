@@ -1,4 +1,7 @@
-use crate::vector::V3;
+use std::ops;
+use crate::vector::{V3, V4};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Matrix3 {
     pub i_hat : V3, // what x will be transformed by
     pub j_hat : V3, // y 
@@ -7,7 +10,7 @@ pub struct Matrix3 {
 impl Matrix3 {
     // transform a vector with this matrix
     pub fn transform_vec3(&self, vector : V3) -> V3 {
-        return vector.x * self.i_hat + vector.y * self.j_hat + vector.z * self.k_hat;
+        vector.x * self.i_hat + vector.y * self.j_hat + vector.z * self.k_hat
     }
     
     // rotation matrix generation 
@@ -54,8 +57,312 @@ impl Matrix3 {
             k_hat
         }
     }
+
+    #[allow(dead_code)]
+    pub fn identity() -> Self {
+        Self::new(
+            V3::new(1.0, 0.0, 0.0),
+            V3::new(0.0, 1.0, 0.0),
+            V3::new(0.0, 0.0, 1.0)
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn scaling(factors : V3) -> Self {
+        Self::new(
+            V3::new(factors.x, 0.0, 0.0),
+            V3::new(0.0, factors.y, 0.0),
+            V3::new(0.0, 0.0, factors.z)
+        )
+    }
+
+    // rotation of theta radians around the unit vector axis (rodrigues)
+    #[allow(dead_code)]
+    pub fn rotation_axis(axis : V3, theta : f32) -> Self {
+        let rotate = |v : V3| {
+            v * f32::cos(theta) + axis.cross(v) * f32::sin(theta) + axis * axis.dot(v) * (1.0 - f32::cos(theta))
+        };
+        Self::new(
+            rotate(V3::new(1.0, 0.0, 0.0)),
+            rotate(V3::new(0.0, 1.0, 0.0)),
+            rotate(V3::new(0.0, 0.0, 1.0))
+        )
+    }
+
+    // rows of the matrix, i.e. the columns of the transpose
+    pub fn rows(&self) -> [V3; 3] {
+        [
+            V3::new(self.i_hat.x, self.j_hat.x, self.k_hat.x),
+            V3::new(self.i_hat.y, self.j_hat.y, self.k_hat.y),
+            V3::new(self.i_hat.z, self.j_hat.z, self.k_hat.z),
+        ]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let [r0, r1, r2] = self.rows();
+        Self::new(r0, r1, r2)
+    }
+
+    // scalar triple product of the basis vectors
+    pub fn determinant(&self) -> f32 {
+        self.i_hat.dot(self.j_hat.cross(self.k_hat))
+    }
+
+    // the rows of the inverse are the pairwise cross products of the columns over the determinant.
+    // None if the matrix is singular
+    #[allow(dead_code)]
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if f32::abs(det) < 1e-8 {
+            return None;
+        }
+        let r0 = self.j_hat.cross(self.k_hat) / det;
+        let r1 = self.k_hat.cross(self.i_hat) / det;
+        let r2 = self.i_hat.cross(self.j_hat) / det;
+        Some(Self::new(r0, r1, r2).transpose())
+    }
+}
+
+// the * operator applies the matrix, so a * b is "b first, then a" and a * v is a.transform_vec3(v)
+impl ops::Mul<V3> for Matrix3 {
+    type Output = V3;
+    fn mul(self, rh : V3) -> V3 {
+        self.transform_vec3(rh)
+    }
+}
+
+impl ops::Mul<Matrix3> for Matrix3 {
+    type Output = Matrix3;
+    fn mul(self, rh : Matrix3) -> Matrix3 {
+        Matrix3::new(
+            self.transform_vec3(rh.i_hat),
+            self.transform_vec3(rh.j_hat),
+            self.transform_vec3(rh.k_hat)
+        )
+    }
+}
+
+impl ops::Mul<f32> for Matrix3 {
+    type Output = Matrix3;
+    fn mul(self, rh : f32) -> Matrix3 {
+        Matrix3::new(self.i_hat * rh, self.j_hat * rh, self.k_hat * rh)
+    }
+}
+
+// 4x4 matrix in row major order, used for affine transforms in homogeneous coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m : [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m : [[f32; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // embeds a 3x3 linear map, the basis vectors become the columns
+    pub fn from_matrix3(mat : &Matrix3) -> Self {
+        let (i, j, k) = (mat.i_hat, mat.j_hat, mat.k_hat);
+        Self::new([
+            [i.x, j.x, k.x, 0.0],
+            [i.y, j.y, k.y, 0.0],
+            [i.z, j.z, k.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset : V3) -> Self {
+        let mut mat = Self::identity();
+        mat.m[0][3] = offset.x;
+        mat.m[1][3] = offset.y;
+        mat.m[2][3] = offset.z;
+        mat
+    }
+
+    pub fn scaling(factors : V3) -> Self {
+        let mut mat = Self::identity();
+        mat.m[0][0] = factors.x;
+        mat.m[1][1] = factors.y;
+        mat.m[2][2] = factors.z;
+        mat
+    }
+
+    pub fn rotation_x(theta : f32) -> Self {
+        Self::from_matrix3(&Matrix3::rotation_x(theta))
+    }
+    pub fn rotation_y(theta : f32) -> Self {
+        Self::from_matrix3(&Matrix3::rotation_y(theta))
+    }
+    pub fn rotation_z(theta : f32) -> Self {
+        Self::from_matrix3(&Matrix3::rotation_z(theta))
+    }
+
+    // the upper left 3x3 block, i.e. the linear part of an affine transform
+    pub fn to_matrix3(self) -> Matrix3 {
+        let m = &self.m;
+        Matrix3::new(
+            V3::new(m[0][0], m[1][0], m[2][0]),
+            V3::new(m[0][1], m[1][1], m[2][1]),
+            V3::new(m[0][2], m[1][2], m[2][2])
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut out = [[0.0; 4]; 4];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, val) in row.iter_mut().enumerate() {
+                *val = self.m[c][r];
+            }
+        }
+        Self::new(out)
+    }
+
+    // laplace expansion along the first row
+    #[allow(dead_code)]
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        let minor = |col : usize| {
+            let cols : Vec<usize> = (0..4).filter(|&c| c != col).collect();
+            let at = |r : usize, c : usize| m[r][cols[c]];
+            at(1, 0) * (at(2, 1) * at(3, 2) - at(2, 2) * at(3, 1))
+                - at(1, 1) * (at(2, 0) * at(3, 2) - at(2, 2) * at(3, 0))
+                + at(1, 2) * (at(2, 0) * at(3, 1) - at(2, 1) * at(3, 0))
+        };
+        m[0][0] * minor(0) - m[0][1] * minor(1) + m[0][2] * minor(2) - m[0][3] * minor(3)
+    }
+
+    // gauss-jordan elimination with partial pivoting. None if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&x, &y| f32::abs(a[x][col]).total_cmp(&f32::abs(a[y][col])))
+                .unwrap();
+            if f32::abs(a[pivot][col]) < 1e-8 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for c in 0..4 {
+                a[col][c] *= scale;
+                inv[col][c] *= scale;
+            }
+            for r in 0..4 {
+                if r == col {
+                    continue;
+                }
+                let factor = a[r][col];
+                for c in 0..4 {
+                    a[r][c] -= factor * a[col][c];
+                    inv[r][c] -= factor * inv[col][c];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    // transform a point, i.e. w = 1 so translation applies
+    pub fn transform_point(&self, p : V3) -> V3 {
+        let m = &self.m;
+        V3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    // transform a direction, i.e. w = 0 so translation is ignored
+    pub fn transform_vec3(&self, v : V3) -> V3 {
+        let m = &self.m;
+        V3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl ops::Mul<V4> for Matrix4 {
+    type Output = V4;
+    fn mul(self, rh : V4) -> V4 {
+        let row = |r : usize| V4::new(self.m[r][0], self.m[r][1], self.m[r][2], self.m[r][3]).dot(rh);
+        V4::new(row(0), row(1), row(2), row(3))
+    }
 }
 
-// op overloads that call transform? Appropriating arithmetic operators for matrix purposes might
-// be bad practice. OTOH we get to overload the * operator so that it works with matrices and
-// vectors without two different function names
+impl ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rh : Matrix4) -> Matrix4 {
+        let mut out = [[0.0; 4]; 4];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, val) in row.iter_mut().enumerate() {
+                *val = (0..4).map(|k| self.m[r][k] * rh.m[k][c]).sum();
+            }
+        }
+        Matrix4::new(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : V3, b : V3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    fn close4(a : &Matrix4, b : &Matrix4) -> bool {
+        (0..4).all(|r| (0..4).all(|c| f32::abs(a.m[r][c] - b.m[r][c]) < 1e-4))
+    }
+
+    #[test]
+    fn matrix3_inverse_roundtrips() {
+        let mat = Matrix3::rotation_axis(V3::new(1.0, 1.0, 0.0).normalized(), 0.7) * Matrix3::scaling(V3::new(2.0, 0.5, 3.0));
+        let inv = mat.inverse().unwrap();
+        let v = V3::new(0.3, -1.2, 4.0);
+        assert!(close(inv * (mat * v), v));
+        assert!(f32::abs(mat.determinant() - 3.0) < 1e-4);
+        assert!(Matrix3::scaling(V3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn matrix3_product_composes() {
+        let a = Matrix3::rotation_x(0.4);
+        let b = Matrix3::rotation_y(-1.1);
+        let v = V3::new(1.0, 2.0, 3.0);
+        assert!(close((a * b) * v, a * (b * v)));
+        // rotations are orthogonal
+        assert!(close(a.transpose() * (a * v), v));
+    }
+
+    #[test]
+    fn rotation_axis_matches_fixed_axes() {
+        let v = V3::new(0.2, -0.5, 1.5);
+        let axis = Matrix3::rotation_axis(V3::new(0.0, 0.0, 1.0), 0.9);
+        assert!(close(axis * v, Matrix3::rotation_z(0.9) * v));
+    }
+
+    #[test]
+    fn matrix4_inverse_and_determinant() {
+        let mat = Matrix4::translation(V3::new(1.0, -2.0, 3.0)) * Matrix4::rotation_y(0.3) * Matrix4::scaling(V3::new(2.0, 2.0, 0.5));
+        assert!(f32::abs(mat.determinant() - 2.0) < 1e-4);
+        let inv = mat.inverse().unwrap();
+        assert!(close4(&(mat * inv), &Matrix4::identity()));
+        let p = V3::new(0.5, 0.5, -1.0);
+        assert!(close(inv.transform_point(mat.transform_point(p)), p));
+        let h = mat * V4::from_v3(p, 1.0);
+        assert!(close(h.xyz(), mat.transform_point(p)));
+    }
+}
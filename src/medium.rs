@@ -0,0 +1,179 @@
+use std::f32::consts::PI;
+use crate::vector::{V2, V3};
+use crate::sampler::Sampler;
+use crate::shape::RayTraceShape;
+
+// Henyey-Greenstein phase function, how a medium scatters light by the angle it turns. g is the
+// average cosine of that angle: 0 scatters evenly, towards 1 mostly onwards like fog and haze do
+// and below 0 mostly back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HenyeyGreenstein {
+    pub g : f32,
+}
+
+impl HenyeyGreenstein {
+    // density of light travelling along -wo scattering towards wi, or by reciprocity light
+    // arriving from wi leaving towards wo. both are unit vectors pointing away from the point
+    pub fn p(&self, wo : V3, wi : V3) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g + 2.0 * g * wo.dot(wi);
+        (1.0 - g * g) / (4.0 * PI * denom * f32::sqrt(denom))
+    }
+
+    // direction wi distributed exactly like p, with its density
+    pub fn sample(&self, wo : V3, u : V2) -> (V3, f32) {
+        let g = self.g;
+        // cosine of the angle to the direction the light was travelling in, -wo
+        let cos_theta = if f32::abs(g) < 1e-3 {
+            1.0 - 2.0 * u.x
+        }
+        else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.x);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = f32::sqrt(f32::max(1.0 - cos_theta * cos_theta, 0.0));
+        let phi = 2.0 * PI * u.y;
+        let forward = -wo;
+        let (tangent, bitangent) = forward.orthonormal_basis();
+        let wi = tangent * (sin_theta * f32::cos(phi)) + bitangent * (sin_theta * f32::sin(phi)) + forward * cos_theta;
+        (wi, self.p(wo, wi))
+    }
+}
+
+// Outcome of tracking a ray through a medium. weight is what the path throughput gets multiplied
+// by whether or not it scattered.
+#[derive(Clone, Copy, Debug)]
+pub struct MediumSample {
+    pub scatter : Option<f32>, // distance along the ray at which it scatters, None if it got through
+    pub weight : V3,
+    pub emission : V3, // light the medium gave off along the way, before weight is applied
+}
+
+// Something light travels through that absorbs and scatters it along the way. Coefficients are
+// per unit of distance, the distance being in world units.
+pub trait Medium : Send + Sync {
+    // decides where a ray from origin along the unit vector dir scatters before t_max, or that it
+    // makes it all the way
+    fn sample(&self, origin : V3, dir : V3, t_max : f32, sampler : &mut dyn Sampler) -> MediumSample;
+    // share of light per channel that makes it distance along dir from origin
+    fn transmittance(&self, origin : V3, dir : V3, distance : f32, sampler : &mut dyn Sampler) -> V3;
+    fn phase(&self) -> HenyeyGreenstein;
+}
+
+// A medium filling the inside of a closed shape. The shape isn't a surface: rays go straight
+// through it, it only marks where the medium starts. Volumes shouldn't overlap each other.
+pub struct Volume {
+    pub boundary : Box<dyn RayTraceShape>,
+    pub medium : Box<dyn Medium>,
+}
+
+// The same density everywhere, like fog, smoke that has settled or murky water.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HomogeneousMedium {
+    pub absorption : V3,
+    pub scattering : V3,
+    pub g : f32,
+}
+
+impl HomogeneousMedium {
+    // grey fog that scatters all the light it takes out of a ray
+    pub fn fog(density : f32, g : f32) -> Self {
+        HomogeneousMedium {
+            absorption : V3::zero(),
+            scattering : V3::new(density, density, density),
+            g,
+        }
+    }
+
+    pub fn extinction(&self) -> V3 {
+        self.absorption + self.scattering
+    }
+}
+
+pub fn component(v : V3, channel : usize) -> f32 {
+    match channel {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// Beer-Lambert, e^(-extinction * distance) per channel. channels that don't attenuate at all
+// stay at 1 over infinite distances
+pub fn beer_lambert(extinction : V3, distance : f32) -> V3 {
+    let channel = |sigma : f32| if sigma <= 0.0 { 1.0 } else { f32::exp(-sigma * distance) };
+    V3::new(channel(extinction.x), channel(extinction.y), channel(extinction.z))
+}
+
+impl Medium for HomogeneousMedium {
+    // samples the distance with the extinction of one channel picked at random, and weights by
+    // the average density over all three so colored media stay unbiased (pbrt's spectral MIS)
+    fn sample(&self, _origin : V3, _dir : V3, t_max : f32, sampler : &mut dyn Sampler) -> MediumSample {
+        let channel = usize::min((sampler.get_1d() * 3.0) as usize, 2);
+        let u = sampler.get_1d();
+        let sigma_t = self.extinction();
+        let sigma = component(sigma_t, channel);
+        let t = if sigma > 0.0 { -f32::ln(1.0 - u) / sigma } else { f32::INFINITY };
+        let scattered = t < t_max;
+        let transmittance = beer_lambert(sigma_t, f32::min(t, t_max));
+        let density = if scattered { transmittance.mul_elem(sigma_t) } else { transmittance };
+        let pdf = (density.x + density.y + density.z) / 3.0;
+        if pdf <= 0.0 {
+            return MediumSample { scatter : None, weight : V3::zero(), emission : V3::zero() };
+        }
+        if scattered {
+            MediumSample { scatter : Some(t), weight : transmittance.mul_elem(self.scattering) / pdf, emission : V3::zero() }
+        }
+        else {
+            MediumSample { scatter : None, weight : transmittance / pdf, emission : V3::zero() }
+        }
+    }
+
+    fn transmittance(&self, _origin : V3, _dir : V3, distance : f32, _sampler : &mut dyn Sampler) -> V3 {
+        beer_lambert(self.extinction(), distance)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        HenyeyGreenstein { g : self.g }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{SamplerKind, mean_over_samples};
+
+    #[test]
+    fn henyey_greenstein_samples_match_its_density() {
+        let phase = HenyeyGreenstein { g : 0.6 };
+        let wo = V3::new(0.0, 0.0, -1.0);
+        let mean_cos = mean_over_samples(4096, |sampler| {
+            let (wi, pdf) = phase.sample(wo, sampler.get_2d());
+            assert!(f32::abs(wi.magnitude() - 1.0) < 1e-4);
+            assert!(f32::abs(pdf - phase.p(wo, wi)) < 1e-3 * pdf);
+            wi.dot(-wo)
+        });
+        assert!(f32::abs(mean_cos - 0.6) < 0.01);
+    }
+
+    #[test]
+    fn free_flights_average_to_the_transmittance() {
+        let medium = HomogeneousMedium {
+            absorption : V3::new(0.2, 0.5, 1.0),
+            scattering : V3::new(0.3, 0.3, 0.3),
+            g : 0.0,
+        };
+        // paths that get through carry the transmittance, those that scatter carry the albedo
+        let split = |scatters : bool| mean_over_samples(8192, |sampler| {
+            let sample = medium.sample(V3::zero(), V3::new(0.0, 0.0, 1.0), 2.0, sampler);
+            if sample.scatter.is_some() == scatters { sample.weight } else { V3::zero() }
+        });
+        let (through, scattered) = (split(false), split(true));
+        let expected = medium.transmittance(V3::zero(), V3::new(0.0, 0.0, 1.0), 2.0, SamplerKind::Sobol.build(1, 0).as_mut());
+        assert!((through - expected).magnitude() < 0.01);
+        // the scattered share is the albedo times everything that didn't get through
+        let albedo = V3::new(0.3 / 0.5, 0.3 / 0.8, 0.3 / 1.3);
+        let expected_scattered = (V3::new(1.0, 1.0, 1.0) - expected).mul_elem(albedo);
+        assert!((scattered - expected_scattered).magnitude() < 0.01);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::shape::RayTraceShape;
+use crate::material::Material;
+use crate::light_tree::LightBounds;
+
+// A triangle mesh with one material. Triangles index into positions and face the side their
+// vertices wind counter clockwise around, which is the side an emissive mesh shines from.
+// Intersection tests every triangle, so this suits the small meshes used for lights and props.
+#[derive(Clone)]
+pub struct Mesh {
+    pub positions : Vec<V3>,
+    pub triangles : Vec<[usize; 3]>,
+    pub material : Material,
+    // running total of the triangle areas, for picking triangles proportional to their size
+    area_cdf : Vec<f32>,
+}
+
+impl Mesh {
+    pub fn new(positions : Vec<V3>, triangles : Vec<[usize; 3]>, material : Material) -> Self {
+        assert!(!triangles.is_empty(), "a mesh needs at least one triangle");
+        assert!(triangles.iter().flatten().all(|&idx| idx < positions.len()), "mesh triangle indexes a missing position");
+        let mut total = 0.0;
+        let mut area_cdf = Vec::with_capacity(triangles.len());
+        let mut mesh = Mesh {
+            positions,
+            triangles,
+            material,
+            area_cdf : Vec::new(),
+        };
+        for idx in 0..mesh.triangles.len() {
+            total += mesh.triangle_area(idx);
+            area_cdf.push(total);
+        }
+        mesh.area_cdf = area_cdf;
+        mesh
+    }
+
+    fn corners(&self, idx : usize) -> (V3, V3, V3) {
+        let [a, b, c] = self.triangles[idx];
+        (self.positions[a], self.positions[b], self.positions[c])
+    }
+
+    fn triangle_area(&self, idx : usize) -> f32 {
+        let (a, b, c) = self.corners(idx);
+        0.5 * (b - a).cross(c - a).magnitude()
+    }
+
+    fn triangle_normal(&self, idx : usize) -> V3 {
+        let (a, b, c) = self.corners(idx);
+        (b - a).cross(c - a).normalized()
+    }
+
+    // Möller-Trumbore
+    fn intersect_triangle(&self, idx : usize, ray : &Ray) -> Option<f32> {
+        let (a, b, c) = self.corners(idx);
+        let edge_one = b - a;
+        let edge_two = c - a;
+        let p = ray.dir.cross(edge_two);
+        let det = edge_one.dot(p);
+        if f32::abs(det) < 1e-10 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = ray.origin - a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge_one);
+        let v = ray.dir.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge_two.dot(q) * inv_det;
+        if t > 0.001 { Some(t) } else { None }
+    }
+
+    // triangle the point lies on, the one whose plane it is closest to among those containing it.
+    // for points on none of them, the closest plane of all
+    fn triangle_at(&self, point : V3) -> usize {
+        let mut best = (0, false, f32::INFINITY);
+        for idx in 0..self.triangles.len() {
+            let (a, b, c) = self.corners(idx);
+            let n = (b - a).cross(c - a);
+            let inside = (b - a).cross(point - a).dot(n) >= -1e-6
+                && (c - b).cross(point - b).dot(n) >= -1e-6
+                && (a - c).cross(point - c).dot(n) >= -1e-6;
+            let distance = f32::abs((point - a).dot(n.normalized()));
+            if (inside, -distance) > (best.1, -best.2) {
+                best = (idx, inside, distance);
+            }
+        }
+        best.0
+    }
+}
+
+impl RayTraceShape for Mesh {
+    fn intersect(&self, ray : Ray) -> Option<f32> {
+        self.intersect_part(ray).map(|(t, _)| t)
+    }
+
+    // the part is the index of the triangle that was hit
+    fn intersect_part(&self, ray : Ray) -> Option<(f32, usize)> {
+        (0..self.triangles.len())
+            .filter_map(|idx| self.intersect_triangle(idx, &ray).map(|t| (t, idx)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    fn normal_at(&self, point : V3, _time : f32) -> V3 {
+        self.triangle_normal(self.triangle_at(point))
+    }
+
+    fn part_normal(&self, _point : V3, part : usize, _time : f32) -> V3 {
+        self.triangle_normal(part)
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    // picks a triangle by area with u.x, then reuses what is left of u.x inside it
+    fn sample_point(&self, u : V2, _time : f32) -> (V3, V3) {
+        let target = u.x * self.area();
+        let idx = usize::min(self.area_cdf.partition_point(|&total| total <= target), self.triangles.len() - 1);
+        let start = if idx == 0 { 0.0 } else { self.area_cdf[idx - 1] };
+        let width = self.area_cdf[idx] - start;
+        let u0 = if width > 0.0 { f32::clamp((target - start) / width, 0.0, 1.0) } else { 0.5 };
+
+        // uniform point in the triangle
+        let (a, b, c) = self.corners(idx);
+        let s = f32::sqrt(u0);
+        let point = a * (1.0 - s) + b * (s * (1.0 - u.y)) + c * (s * u.y);
+        (point, self.triangle_normal(idx))
+    }
+
+    // the normal cone starts from the area weighted average normal and widens to hold every face
+    fn light_bounds(&self) -> LightBounds {
+        let first = self.positions[0];
+        let min = self.positions.iter().fold(first, |min, &p| min.min_elem(p));
+        let max = self.positions.iter().fold(first, |max, &p| max.max_elem(p));
+        let mut axis = V3::zero();
+        for idx in 0..self.triangles.len() {
+            axis += self.triangle_normal(idx) * self.triangle_area(idx);
+        }
+        let (axis, cos_theta_o) = if axis.magnitude_squared() > 1e-12 {
+            let axis = axis.normalized();
+            let cos_theta_o = (0..self.triangles.len())
+                .map(|idx| axis.dot(self.triangle_normal(idx)))
+                .fold(1.0, f32::min);
+            (axis, cos_theta_o)
+        }
+        else {
+            (V3::new(0.0, 1.0, 0.0), -1.0)
+        };
+        LightBounds::surface(min, max, axis, cos_theta_o, self.material.emission, self.area())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Col3;
+
+    // unit square in the xy plane facing +z, split into two triangles of different size
+    fn square() -> Mesh {
+        let positions = vec![V3::new(0.0, 0.0, 0.0), V3::new(1.0, 0.0, 0.0), V3::new(1.0, 1.0, 0.0), V3::new(0.0, 1.0, 0.0)];
+        Mesh::new(positions, vec![[0, 1, 2], [0, 2, 3]], Material::light(Col3::white(), 1.0))
+    }
+
+    #[test]
+    fn ray_hits_nearest_triangle() {
+        let mesh = square();
+        let ray = Ray::new(V3::new(0.25, 0.75, 2.0), V3::new(0.0, 0.0, -1.0), 1);
+        assert_eq!(mesh.intersect(ray), Some(2.0));
+        assert_eq!(mesh.normal_at(V3::new(0.25, 0.75, 0.0), 0.0), V3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.intersect_part(ray).map(|(_, triangle)| triangle), Some(1));
+        let miss = Ray::new(V3::new(1.5, 0.5, 2.0), V3::new(0.0, 0.0, -1.0), 1);
+        assert_eq!(mesh.intersect(miss), None);
+    }
+
+    #[test]
+    fn samples_cover_the_surface() {
+        let mesh = square();
+        assert!(f32::abs(mesh.area() - 1.0) < 1e-6);
+        for (i, j) in [(0.1, 0.2), (0.49, 0.9), (0.51, 0.1), (0.99, 0.99)] {
+            let (point, normal) = mesh.sample_point(V2::new(i, j), 0.0);
+            assert!((0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y) && point.z == 0.0);
+            assert_eq!(normal, V3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one triangle")]
+    fn empty_meshes_are_rejected() {
+        Mesh::new(vec![V3::zero()], Vec::new(), Material::light(Col3::white(), 1.0));
+    }
+}
@@ -1,7 +1,6 @@
-use crate::vector::V3;
+use crate::vector::{V2, V3};
 use crate::ray::Ray;
 use crate::shape::RayTraceShape;
-use crate::color::Col3;
 use crate::material::Material;
 
 // A plane spanned by two basis vectors, offset by an origin vector.
@@ -17,10 +16,10 @@ impl Plane {
     pub fn normal(&self) -> V3 {
         self.base_one.cross(self.base_two)
     }
+    #[allow(dead_code)]
     pub fn project(&self, source : V3) -> V3 {
         let perp_pi_v = self.normal().project(source);
-        let proj_pi_v = source - perp_pi_v;
-        proj_pi_v
+        source - perp_pi_v
     }
 }
 
@@ -41,13 +40,21 @@ impl RayTraceShape for Plane {
     
     }
 
-    fn reflect(&self, ray : &mut Ray, interx : V3) {
-        let proj_n_raydir = (V3::zero() + self.normal()).project(ray.dir);
-        let new_dir = V3::zero() + ((2.0 * proj_n_raydir) - ray.dir); // reflection formula
 
-        ray.origin = interx;
-        ray.dir = new_dir;
-        self.material.shade_ray_reflection(ray, self.normal());
-        ray.bounces_remaining = ray.bounces_remaining - 1;
+    fn normal_at(&self, _point : V3, _time : f32) -> V3 {
+        self.normal().normalized()
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        f32::INFINITY
+    }
+
+    // there is no uniform distribution over an infinite plane, so this is only the origin
+    fn sample_point(&self, _u : V2, _time : f32) -> (V3, V3) {
+        (self.origin, self.normal().normalized())
     }
 }
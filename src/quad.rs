@@ -0,0 +1,71 @@
+use crate::vector::{V2, V3};
+use crate::ray::Ray;
+use crate::shape::RayTraceShape;
+use crate::material::Material;
+use crate::light_tree::LightBounds;
+
+// A parallelogram with one corner at origin and the two edges base_one and base_two, like a Plane
+// cut down to size. The front side faces base_one x base_two, which is where an emissive quad
+// shines.
+#[derive(Clone, Copy)]
+pub struct Quad {
+    pub origin : V3,
+    pub base_one : V3,
+    pub base_two : V3,
+    pub material : Material,
+}
+
+impl Quad {
+    pub fn normal(&self) -> V3 {
+        self.base_one.cross(self.base_two).normalized()
+    }
+}
+
+impl RayTraceShape for Quad {
+    fn intersect(&self, ray : Ray) -> Option<f32> {
+        let n = self.base_one.cross(self.base_two);
+        let denom = n.dot(ray.dir);
+        if f32::abs(denom) < 1e-8 {
+            return None;
+        }
+        let t = -n.dot(ray.origin - self.origin) / denom;
+        if t <= 0.001 {
+            return None;
+        }
+        // coordinates of the hit along both edges, which also works for non rectangular quads
+        let d = ray.origin + ray.dir * t - self.origin;
+        let n_squared = n.magnitude_squared();
+        let s = d.cross(self.base_two).dot(n) / n_squared;
+        let r = self.base_one.cross(d).dot(n) / n_squared;
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&r) {
+            Some(t)
+        }
+        else {
+            None
+        }
+    }
+
+
+    fn normal_at(&self, _point : V3, _time : f32) -> V3 {
+        self.normal()
+    }
+
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        self.base_one.cross(self.base_two).magnitude()
+    }
+
+    fn sample_point(&self, u : V2, _time : f32) -> (V3, V3) {
+        (self.origin + self.base_one * u.x + self.base_two * u.y, self.normal())
+    }
+
+    fn light_bounds(&self) -> LightBounds {
+        let corners = [self.origin + self.base_one, self.origin + self.base_two, self.origin + self.base_one + self.base_two];
+        let min = corners.iter().fold(self.origin, |min, &corner| min.min_elem(corner));
+        let max = corners.iter().fold(self.origin, |max, &corner| max.max_elem(corner));
+        LightBounds::surface(min, max, self.normal(), 1.0, self.material.emission, self.area())
+    }
+}
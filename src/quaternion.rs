@@ -0,0 +1,192 @@
+use std::ops;
+use crate::vector::V3;
+use crate::matrix::Matrix3;
+
+// Quaternion w + xi + yj + zk. Unit quaternions represent rotations without gimbal lock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w : f32,
+    pub x : f32,
+    pub y : f32,
+    pub z : f32,
+}
+
+impl Quaternion {
+    pub fn new(w : f32, x : f32, y : f32, z : f32) -> Self {
+        Quaternion {w, x, y, z}
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    // rotation of theta radians around the unit vector axis
+    pub fn from_axis_angle(axis : V3, theta : f32) -> Self {
+        let half = 0.5 * theta;
+        let v = axis * f32::sin(half);
+        Self::new(f32::cos(half), v.x, v.y, v.z)
+    }
+
+    // extracts the rotation from an orthonormal basis (Shepperd's method, picking the largest
+    // diagonal term for numerical stability)
+    pub fn from_matrix3(mat : &Matrix3) -> Self {
+        let [r0, r1, r2] = mat.rows();
+        let (m00, m01, m02) = (r0.x, r0.y, r0.z);
+        let (m10, m11, m12) = (r1.x, r1.y, r1.z);
+        let (m20, m21, m22) = (r2.x, r2.y, r2.z);
+        let trace = m00 + m11 + m22;
+        let q = if trace > 0.0 {
+            let s = 2.0 * f32::sqrt(trace + 1.0);
+            Self::new(0.25 * s, (m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s)
+        }
+        else if m00 > m11 && m00 > m22 {
+            let s = 2.0 * f32::sqrt(1.0 + m00 - m11 - m22);
+            Self::new((m21 - m12) / s, 0.25 * s, (m01 + m10) / s, (m02 + m20) / s)
+        }
+        else if m11 > m22 {
+            let s = 2.0 * f32::sqrt(1.0 + m11 - m00 - m22);
+            Self::new((m02 - m20) / s, (m01 + m10) / s, 0.25 * s, (m12 + m21) / s)
+        }
+        else {
+            let s = 2.0 * f32::sqrt(1.0 + m22 - m00 - m11);
+            Self::new((m10 - m01) / s, (m02 + m20) / s, (m12 + m21) / s, 0.25 * s)
+        };
+        q.normalized()
+    }
+
+    pub fn vector_part(&self) -> V3 {
+        V3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(&self, rh : Quaternion) -> f32 {
+        self.w * rh.w + self.x * rh.x + self.y * rh.y + self.z * rh.z
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        f32::sqrt(self.dot(*self))
+    }
+
+    pub fn normalized(&self) -> Self {
+        *self * (1.0 / self.magnitude())
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    // for unit quaternions the inverse is the conjugate
+    #[allow(dead_code)]
+    pub fn inverse(&self) -> Self {
+        self.conjugate() * (1.0 / self.dot(*self))
+    }
+
+    // rotate a vector by this unit quaternion, q v q*
+    pub fn rotate(&self, v : V3) -> V3 {
+        let u = self.vector_part();
+        let t = 2.0 * u.cross(v);
+        v + self.w * t + u.cross(t)
+    }
+
+    pub fn to_matrix3(self) -> Matrix3 {
+        Matrix3::new(
+            self.rotate(V3::new(1.0, 0.0, 0.0)),
+            self.rotate(V3::new(0.0, 1.0, 0.0)),
+            self.rotate(V3::new(0.0, 0.0, 1.0))
+        )
+    }
+
+    // spherical linear interpolation along the shorter arc, falls back to a normalized lerp when
+    // the two rotations are nearly identical
+    pub fn slerp(&self, to : Quaternion, t : f32) -> Self {
+        let mut to = to;
+        let mut cos_theta = self.dot(to);
+        if cos_theta < 0.0 {
+            to = to * -1.0;
+            cos_theta = -cos_theta;
+        }
+        if cos_theta > 0.9995 {
+            return (*self * (1.0 - t) + to * t).normalized();
+        }
+        let theta = f32::acos(cos_theta);
+        let sin_theta = f32::sin(theta);
+        let a = f32::sin((1.0 - t) * theta) / sin_theta;
+        let b = f32::sin(t * theta) / sin_theta;
+        *self * a + to * b
+    }
+}
+
+// hamilton product, a * b rotates by b first and then by a
+impl ops::Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rh : Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * rh.w - self.x * rh.x - self.y * rh.y - self.z * rh.z,
+            self.w * rh.x + self.x * rh.w + self.y * rh.z - self.z * rh.y,
+            self.w * rh.y - self.x * rh.z + self.y * rh.w + self.z * rh.x,
+            self.w * rh.z + self.x * rh.y - self.y * rh.x + self.z * rh.w,
+        )
+    }
+}
+
+impl ops::Mul<f32> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rh : f32) -> Quaternion {
+        Quaternion::new(self.w * rh, self.x * rh, self.y * rh, self.z * rh)
+    }
+}
+
+impl ops::Add<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn add(self, rh : Quaternion) -> Quaternion {
+        Quaternion::new(self.w + rh.w, self.x + rh.x, self.y + rh.y, self.z + rh.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn close(a : V3, b : V3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn rotate_matches_matrix() {
+        let axis = V3::new(1.0, -2.0, 0.5).normalized();
+        let q = Quaternion::from_axis_angle(axis, 1.3);
+        let v = V3::new(0.4, 1.0, -2.0);
+        assert!(close(q.rotate(v), Matrix3::rotation_axis(axis, 1.3) * v));
+        assert!(close(q.to_matrix3() * v, q.rotate(v)));
+    }
+
+    #[test]
+    fn product_composes_rotations() {
+        let a = Quaternion::from_axis_angle(V3::new(0.0, 1.0, 0.0), 0.5);
+        let b = Quaternion::from_axis_angle(V3::new(1.0, 0.0, 0.0), -1.2);
+        let v = V3::new(1.0, 2.0, 3.0);
+        assert!(close((a * b).rotate(v), a.rotate(b.rotate(v))));
+        assert!(close(a.inverse().rotate(a.rotate(v)), v));
+    }
+
+    #[test]
+    fn from_matrix3_roundtrips() {
+        for (axis, theta) in [(V3::new(0.0, 0.0, 1.0), 0.3), (V3::new(1.0, 1.0, 1.0).normalized(), 2.9), (V3::new(0.0, 1.0, 0.0), PI)] {
+            let q = Quaternion::from_axis_angle(axis, theta);
+            let back = Quaternion::from_matrix3(&q.to_matrix3());
+            // q and -q are the same rotation
+            assert!(f32::abs(f32::abs(q.dot(back)) - 1.0) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn slerp_interpolates_angle() {
+        let axis = V3::new(0.0, 0.0, 1.0);
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(axis, PI * 0.5);
+        let mid = a.slerp(b, 0.5);
+        assert!(close(mid.rotate(V3::new(1.0, 0.0, 0.0)), V3::new(1.0, 1.0, 0.0).normalized()));
+        assert!(f32::abs(mid.magnitude() - 1.0) < 1e-5);
+        assert!(close(a.slerp(b, 1.0).rotate(V3::new(1.0, 0.0, 0.0)), V3::new(0.0, 1.0, 0.0)));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::vector::V2;
+
+// Source of the uniform [0, 1) numbers a path consumes. Before each sample of a pixel the renderer
+// calls start_pixel_sample, after which every get_1d / get_2d call moves on to the next dimension.
+// Camera samples come first, then each bounce draws its own dimensions, so well distributed
+// samplers spread consecutive samples of a pixel evenly over every dimension.
+// Every number is a pure function of the seed, pixel, sample index and dimension, so the same
+// settings always give the same image no matter in which order or on which thread pixels render,
+// which lets Scene::render_with trace rows in parallel.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, x_idx : u16, y_idx : u16, sample_index : u32);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> V2;
+}
+
+// Which sampler a render uses, see Camera::sampler.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    // white noise, every number independent of the others
+    Independent,
+    // jittered strata, each sample of a pixel lands in its own cell of a grid
+    Stratified,
+    // radical inverse in prime bases, randomized per pixel with a Cranley-Patterson rotation
+    Halton,
+    // Owen scrambled Sobol (0,2) sequence with hashed padding between dimension pairs
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn build(self, samples_per_pixel : u32, seed : u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            SamplerKind::Independent => SamplerKind::Stratified,
+            SamplerKind::Stratified => SamplerKind::Halton,
+            SamplerKind::Halton => SamplerKind::Sobol,
+            SamplerKind::Sobol => SamplerKind::Independent,
+        }
+    }
+}
+
+// lowbias32 integer hash by Chris Wellons
+pub fn hash(mut x : u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+pub fn hash3(a : u32, b : u32, c : u32) -> u32 {
+    hash(a ^ hash(b ^ hash(c)))
+}
+
+// maps 32 random bits to [0, 1), using the top 24 bits so the result never rounds up to 1
+pub fn bits_to_unit(bits : u32) -> f32 {
+    (bits >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+// decorrelates pixels, and whole renders with different seeds
+fn pixel_seed(seed : u32, x_idx : u16, y_idx : u16) -> u32 {
+    hash(seed ^ hash(((x_idx as u32) << 16) | y_idx as u32))
+}
+
+// element i of a pseudo random permutation of 0..len, picked by seed (Kensler 2013)
+fn permutation_element(mut i : u32, len : u32, seed : u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % len
+}
+
+// white noise, hashed from the pixel, sample index and dimension rather than drawn from a global
+// generator so each sample is its own reproducible stream
+pub struct IndependentSampler {
+    seed : u32,
+    pixel : u32,
+    sample_index : u32,
+    dimension : u32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed : u32) -> Self {
+        IndependentSampler {
+            seed,
+            pixel : 0,
+            sample_index : 0,
+            dimension : 0,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x_idx : u16, y_idx : u16, sample_index : u32) {
+        self.pixel = pixel_seed(self.seed, x_idx, y_idx);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let value = bits_to_unit(hash3(self.pixel, self.sample_index, self.dimension));
+        self.dimension += 1;
+        value
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        V2::new(self.get_1d(), self.get_1d())
+    }
+}
+
+// Every dimension is split into samples_per_pixel strata (2d dimensions into a square grid), and
+// sample i of a pixel takes a stratum from a permutation that is different per pixel and dimension.
+pub struct StratifiedSampler {
+    seed : u32,
+    samples_per_pixel : u32,
+    grid_size : u32,
+    pixel : u32,
+    sample_index : u32,
+    dimension : u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel : u32, seed : u32) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        StratifiedSampler {
+            seed,
+            samples_per_pixel,
+            grid_size : f32::ceil(f32::sqrt(samples_per_pixel as f32)) as u32,
+            pixel : 0,
+            sample_index : 0,
+            dimension : 0,
+        }
+    }
+
+    fn jitter(&self, salt : u32) -> f32 {
+        bits_to_unit(hash3(self.pixel ^ salt, self.sample_index, self.dimension))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x_idx : u16, y_idx : u16, sample_index : u32) {
+        self.pixel = pixel_seed(self.seed, x_idx, y_idx);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let seed = hash3(self.pixel, self.dimension, 0x1d);
+        let stratum = permutation_element(self.sample_index % self.samples_per_pixel, self.samples_per_pixel, seed);
+        let value = (stratum as f32 + self.jitter(0x51)) / self.samples_per_pixel as f32;
+        self.dimension += 1;
+        value
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        let cells = self.grid_size * self.grid_size;
+        let seed = hash3(self.pixel, self.dimension, 0x2d);
+        let cell = permutation_element(self.sample_index % cells, cells, seed);
+        let (cx, cy) = (cell % self.grid_size, cell / self.grid_size);
+        let value = V2::new(
+            (cx as f32 + self.jitter(0x53)) / self.grid_size as f32,
+            (cy as f32 + self.jitter(0x59)) / self.grid_size as f32,
+        );
+        self.dimension += 2;
+        value
+    }
+}
+
+const PRIMES : [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+pub fn radical_inverse(base : u32, mut index : u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed : u64 = 0;
+    while index > 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        reversed = reversed * base as u64 + digit as u64;
+        inv_base_n *= inv_base;
+        index = next;
+    }
+    f32::min((reversed as f64 * inv_base_n) as f32, 1.0 - f32::EPSILON * 0.5)
+}
+
+// Dimension d uses the d-th prime as its base. Dimensions past the prime table fall back to
+// hashed white noise, which only happens on very deep paths.
+pub struct HaltonSampler {
+    seed : u32,
+    pixel : u32,
+    sample_index : u32,
+    dimension : u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed : u32) -> Self {
+        HaltonSampler {
+            seed,
+            pixel : 0,
+            sample_index : 0,
+            dimension : 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x_idx : u16, y_idx : u16, sample_index : u32) {
+        self.pixel = pixel_seed(self.seed, x_idx, y_idx);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dim = self.dimension as usize;
+        self.dimension += 1;
+        let rotation = bits_to_unit(hash3(self.pixel, dim as u32, 0x4a));
+        if dim >= PRIMES.len() {
+            return bits_to_unit(hash3(self.pixel, self.sample_index, dim as u32));
+        }
+        let value = radical_inverse(PRIMES[dim], self.sample_index) + rotation;
+        if value >= 1.0 { value - 1.0 } else { value }
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        V2::new(self.get_1d(), self.get_1d())
+    }
+}
+
+// first two dimensions of the Sobol sequence as 32 bit fixed point
+fn sobol_2d(index : u32) -> (u32, u32) {
+    let x = index.reverse_bits();
+    let mut y = 0;
+    let mut direction : u32 = 1 << 31;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+    (x, y)
+}
+
+// Laine-Karras style hash that only lets each bit depend on the bits below it
+fn laine_karras_permutation(mut x : u32, seed : u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scrambling of a fixed point number, flips bits depending on all the more significant bits
+fn nested_uniform_scramble(x : u32, seed : u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Burley 2020, "Practical Hash-based Owen Scrambling". Each pair of dimensions gets its own
+// shuffle of the sample order and its own scramble of a (0,2) sequence, so 1d and 2d projections
+// stay stratified for power of two sample counts without needing high dimensional Sobol tables.
+pub struct SobolSampler {
+    seed : u32,
+    pixel : u32,
+    sample_index : u32,
+    dimension : u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed : u32) -> Self {
+        SobolSampler {
+            seed,
+            pixel : 0,
+            sample_index : 0,
+            dimension : 0,
+        }
+    }
+
+    fn scrambled(&self) -> (u32, u32) {
+        let seed = hash3(self.pixel, self.dimension, 0x5b);
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let (x, y) = sobol_2d(index);
+        (nested_uniform_scramble(x, hash(seed ^ 0xa511e9b3)), nested_uniform_scramble(y, hash(seed ^ 0x63d83595)))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x_idx : u16, y_idx : u16, sample_index : u32) {
+        self.pixel = pixel_seed(self.seed, x_idx, y_idx);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (x, _) = self.scrambled();
+        self.dimension += 1;
+        bits_to_unit(x)
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        let (x, y) = self.scrambled();
+        self.dimension += 2;
+        V2::new(bits_to_unit(x), bits_to_unit(y))
+    }
+}
+
+// average of f over n samples of one pixel from the Sobol sampler, for tests that check an
+// estimator converges to its expected value
+#[cfg(test)]
+pub fn mean_over_samples<T>(n : u32, mut f : impl FnMut(&mut dyn Sampler) -> T) -> T
+where T : std::ops::Add<Output = T> + std::ops::Div<f32, Output = T> {
+    let mut sampler = SamplerKind::Sobol.build(n, 0);
+    let sum = (0..n)
+        .map(|i| {
+            sampler.start_pixel_sample(0, 0, i);
+            f(sampler.as_mut())
+        })
+        .reduce(|a, b| a + b)
+        .expect("mean over no samples");
+    sum / n as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // true if the n samples put exactly one value into each of the n strata of [0, 1)
+    fn is_stratified(values : &[f32]) -> bool {
+        let n = values.len();
+        let mut seen = vec![false; n];
+        for v in values {
+            let stratum = (v * n as f32) as usize;
+            if stratum >= n || seen[stratum] {
+                return false;
+            }
+            seen[stratum] = true;
+        }
+        true
+    }
+
+    fn draw(sampler : &mut dyn Sampler, n : u32) -> (Vec<f32>, Vec<V2>) {
+        let mut ones = Vec::new();
+        let mut twos = Vec::new();
+        for i in 0..n {
+            sampler.start_pixel_sample(3, 7, i);
+            twos.push(sampler.get_2d());
+            ones.push(sampler.get_1d());
+        }
+        (ones, twos)
+    }
+
+    #[test]
+    fn permutation_is_a_bijection() {
+        for len in [1, 5, 16, 33] {
+            let mut seen : Vec<u32> = (0..len).map(|i| permutation_element(i, len, 0xdeadbeef)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!(f32::abs(radical_inverse(3, 5) - 7.0 / 9.0) < 1e-6);
+    }
+
+    fn grid_cells(points : &[V2]) -> Vec<f32> {
+        points.iter().map(|v| (f32::floor(v.y * 4.0) * 4.0 + f32::floor(v.x * 4.0) + 0.5) / 16.0).collect()
+    }
+
+    #[test]
+    fn stratified_fills_every_stratum() {
+        let mut sampler = SamplerKind::Stratified.build(16, 0);
+        let (ones, twos) = draw(sampler.as_mut(), 16);
+        assert!(is_stratified(&ones));
+        assert!(is_stratified(&grid_cells(&twos)));
+    }
+
+    #[test]
+    fn sobol_is_stratified_in_every_projection() {
+        let mut sampler = SamplerKind::Sobol.build(16, 0);
+        let (ones, twos) = draw(sampler.as_mut(), 16);
+        assert!(is_stratified(&ones));
+        assert!(is_stratified(&grid_cells(&twos)));
+        // unlike a jittered grid, (0,2) sequences also stratify each axis of the 2d points on its own
+        assert!(is_stratified(&twos.iter().map(|v| v.x).collect::<Vec<_>>()));
+        assert!(is_stratified(&twos.iter().map(|v| v.y).collect::<Vec<_>>()));
+    }
+}
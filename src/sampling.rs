@@ -0,0 +1,136 @@
+use std::f32::consts::{FRAC_PI_4, FRAC_PI_2, PI};
+use crate::vector::{V2, V3};
+
+// Warps uniform samples in [0, 1)^2 onto other domains. Every mapping is area preserving, so
+// stratified input stays stratified on the output.
+
+// unit disk, Shirley and Chiu's concentric mapping
+pub fn concentric_disk(u : V2) -> V2 {
+    let offset = 2.0 * u - V2::new(1.0, 1.0);
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return V2::zero();
+    }
+    let (r, theta) = if f32::abs(offset.x) > f32::abs(offset.y) {
+        (offset.x, FRAC_PI_4 * (offset.y / offset.x))
+    }
+    else {
+        (offset.y, FRAC_PI_2 - FRAC_PI_4 * (offset.x / offset.y))
+    };
+    r * V2::new(f32::cos(theta), f32::sin(theta))
+}
+
+// regular polygon inscribed in the unit circle, used for bladed apertures. The first sample
+// coordinate picks the wedge and is then reused inside the triangle
+pub fn regular_polygon(u : V2, sides : u8, rotation : f32) -> V2 {
+    let sides = sides.max(3) as f32;
+    let scaled = u.x * sides;
+    let wedge = f32::min(f32::floor(scaled), sides - 1.0);
+    let u0 = scaled - wedge;
+
+    let angle = 2.0 * PI / sides;
+    let a = rotation + wedge * angle;
+    let corner_a = V2::new(f32::cos(a), f32::sin(a));
+    let corner_b = V2::new(f32::cos(a + angle), f32::sin(a + angle));
+
+    // uniform point in the triangle (centre, corner_a, corner_b)
+    let s = f32::sqrt(u0);
+    s * (1.0 - u.y) * corner_a + s * u.y * corner_b
+}
+
+// hemisphere around +z with density cos(theta) / pi, by projecting a disk sample up (Malley's method)
+pub fn cosine_hemisphere(u : V2) -> V3 {
+    let d = concentric_disk(u);
+    let z = f32::sqrt(f32::max(0.0, 1.0 - d.x * d.x - d.y * d.y));
+    V3::new(d.x, d.y, z)
+}
+
+// cone around +z out to the angle whose cosine is cos_max, uniform over solid angle with density
+// 1 / (2 pi (1 - cos_max))
+pub fn uniform_cone(u : V2, cos_max : f32) -> V3 {
+    let z = 1.0 - u.x * (1.0 - cos_max);
+    let r = f32::sqrt(f32::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.y;
+    V3::new(r * f32::cos(phi), r * f32::sin(phi), z)
+}
+
+// turns a direction sampled around +z into one around the unit vector normal
+pub fn to_world(local : V3, normal : V3) -> V3 {
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    tangent * local.x + bitangent * local.y + normal * local.z
+}
+
+// Piecewise constant density over [0, 1) proportional to func, sampled by inverting its CDF.
+pub struct Distribution1D {
+    pub func : Vec<f32>,
+    cdf : Vec<f32>,
+    pub integral : f32,
+}
+
+impl Distribution1D {
+    pub fn new(func : Vec<f32>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + f32::abs(func[i]) / n as f32;
+        }
+        let integral = cdf[n];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            // all zero functions fall back to uniform
+            *value = if integral > 0.0 { *value / integral } else { i as f32 / n as f32 };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    // returns x in [0, 1), its density and the index of the segment it fell into
+    pub fn sample(&self, u : f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let idx = usize::min(self.cdf.partition_point(|&c| c <= u).saturating_sub(1), n - 1);
+        let width = self.cdf[idx + 1] - self.cdf[idx];
+        let du = if width > 0.0 { (u - self.cdf[idx]) / width } else { 0.0 };
+        let x = f32::min((idx as f32 + du) / n as f32, 1.0 - f32::EPSILON);
+        (x, self.pdf_at(idx), idx)
+    }
+
+    pub fn pdf_at(&self, idx : usize) -> f32 {
+        if self.integral > 0.0 { f32::abs(self.func[idx]) / self.integral } else { 1.0 }
+    }
+}
+
+// Piecewise constant density over [0, 1)^2 from a grid of nu x nv values stored row by row, sampled
+// as a marginal distribution over rows and a conditional one within the chosen row.
+pub struct Distribution2D {
+    conditional : Vec<Distribution1D>,
+    marginal : Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func : &[f32], nu : usize, nv : usize) -> Self {
+        let conditional : Vec<Distribution1D> = (0..nv)
+            .map(|v| Distribution1D::new(func[v * nu..(v + 1) * nu].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral).collect());
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    // point in [0, 1)^2 and its density
+    pub fn sample(&self, u : V2) -> (V2, f32) {
+        let (y, pdf_y, row) = self.marginal.sample(u.y);
+        let (x, pdf_x, _) = self.conditional[row].sample(u.x);
+        (V2::new(x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p : V2) -> f32 {
+        let nv = self.conditional.len();
+        let row = usize::min((p.y * nv as f32) as usize, nv - 1);
+        let nu = self.conditional[row].func.len();
+        let col = usize::min((p.x * nu as f32) as usize, nu - 1);
+        self.marginal.pdf_at(row) * self.conditional[row].pdf_at(col)
+    }
+}
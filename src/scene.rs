@@ -1,140 +1,678 @@
-use crate::vector::V3;
+use std::collections::HashMap;
 use crate::ray::Ray;
 use crate::color::Col3;
 use crate::shape::RayTraceShape;
-use crate::matrix::Matrix3;
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::adaptive::{PixelVariance, luminance, sample_count_color};
+use crate::light::{Light, LightSample};
+use crate::environment::EnvironmentMap;
+use crate::light_tree::{LightTree, LightBounds};
+use crate::medium::{Medium, Volume};
+use crate::sampler::Sampler;
+use crate::vector::V3;
+use crate::integrator::Integrator;
 use rayon::prelude::*;
 
+// rows traced together into one film. each band's film also holds the rows its samples spill
+// onto, so taller bands waste less memory on that margin but split the image into fewer tasks
+const BAND_ROWS : u16 = 16;
+
 // TODO
 // bright rays bloom onto adjacent pixels with gaussian convolution
 
+// geometry, lights and the environment change through methods, which keep the light indices and
+// the light tree in step with them
 pub struct Scene {
-    pub geometry : Vec<Box<dyn RayTraceShape>>,
+    geometry : Vec<Box<dyn RayTraceShape>>,
+    lights : Vec<Box<dyn Light>>, // analytic lights, emissive geometry doesn't need listing here
+    environment : Option<EnvironmentMap>, // seen by rays that escape, black without one
+    emitters : Vec<usize>, // geometry indices of the shapes sampled as area lights, by light index
+    emitter_index : HashMap<usize, usize>, // light index of each of those shapes, by shape_address
+    light_tree : Option<LightTree>, // picks lights by their contribution, see build_light_tree
+    pub medium : Option<Box<dyn Medium>>, // fills the space outside all volumes, e.g. fog
+    pub volumes : Vec<Volume>, // media inside closed shapes, only the path integrator scatters in them
     pub camera : Camera
 }
 
-pub struct Camera {
-    pub location : V3,
-    pub rotation_y : f32,
-    pub rotation_x : f32,
-    pub viewport_anchor : V3, // relative to the camera. The botleft corner of the viewport in camera
-                          // space
-    pub size_x : u16,
-    pub size_y : u16,
-    pub rays_per_pixel : u16,
-    pub bounce_depth : u16,
-    pub max_steps : u16, 
-    pub step_len : f32, // discretization of the ray allows for the modelling of non linear space
-}
+impl Scene {
+    pub fn new(geometry : Vec<Box<dyn RayTraceShape>>, lights : Vec<Box<dyn Light>>, camera : Camera) -> Self {
+        let mut scene = Scene {
+            geometry : Vec::new(),
+            lights,
+            environment : None,
+            emitters : Vec::new(),
+            emitter_index : HashMap::new(),
+            light_tree : None,
+            medium : None,
+            volumes : Vec::new(),
+            camera,
+        };
+        scene.set_geometry(geometry);
+        scene
+    }
 
-impl Camera {
-    // returns all the rays for a given pixel, which need to be simulated by the caller. 
-    pub fn generate_rays_for_pixel(&self, x_idx : u16, y_idx : u16) -> Vec<Ray> {
-        // base ray
-        
-        let pixel_stride = (self.viewport_anchor.x * 2.0) as f32 / self.size_x as f32;
-
-        let mut viewport_interx_camera_space = self.viewport_anchor + self.location;         //randomness, to prevent outlier rays
-        let ray_origin = viewport_interx_camera_space;
-        //viewport_interx_camera_space = viewport_interx_camera_space +         
-
-        let ray_dir = viewport_interx_camera_space; 
-        // TODO transform ray_dir with change-of-basis matrix so that self.direction is used
-
-        let ray_color = Col3::white();
-        
-        let mut rays = Vec::with_capacity(self.rays_per_pixel as usize);
-       
-        // sorry for the long line
-        // TODO: split up the rotation transforms etc
-        for i in 0..self.rays_per_pixel {
-            rays.push(Ray {
-                origin : ray_origin,
-                dir : Matrix3::rotation_x(self.rotation_x).transform_vec3(Matrix3::rotation_y(self.rotation_y).transform_vec3((ray_dir + pixel_stride * V3::new(x_idx as f32, y_idx as f32, 0.0) + pixel_stride * V3::new(fastrand::f32() - 0.5, fastrand::f32() - 0.5, 0.0)).normalized())),
-                color : ray_color,
-                bounces_remaining : self.bounce_depth,
-                steps_remaining : self.max_steps,
-                gamma : 0.2
-            });
+    // changing the geometry, lights or environment drops the light tree, see build_light_tree
+    pub fn set_geometry(&mut self, geometry : Vec<Box<dyn RayTraceShape>>) {
+        self.geometry.clear();
+        self.emitters.clear();
+        self.emitter_index.clear();
+        for shape in geometry {
+            self.add_shape(shape);
         }
+    }
 
-        rays
+    pub fn add_shape(&mut self, shape : Box<dyn RayTraceShape>) {
+        // shapes with an emissive material and finite area get sampled as area lights
+        if shape.material().is_emissive() && shape.area().is_finite() {
+            self.emitter_index.insert(shape_address(shape.as_ref()), self.emitters.len());
+            self.emitters.push(self.geometry.len());
+        }
+        self.geometry.push(shape);
+        self.light_tree = None;
     }
-}
 
-impl Scene {
-    pub fn render(&self) -> Vec<Col3> {
-        // TODO write TODO
-        let mut to_ret : Vec<Col3> = Vec::new();
-        for y in 0..self.camera.size_y {
-            for x in (0..self.camera.size_x) {
-                let rays = self.camera.generate_rays_for_pixel(x, y);
-                let mut pix_col_r : u64 = 0;
-                let mut pix_col_g : u64 = 0;
-                let mut pix_col_b : u64 = 0;
-                for mut ray in rays {
-                    let pix_col = self.path_trace_ray(&mut ray);
-                     
-                    pix_col_r = pix_col_r + (pix_col.r as f32 * (ray.gamma)) as u64;
-                    pix_col_g = pix_col_g + (pix_col.g as f32 * (ray.gamma)) as u64;
-                    pix_col_b = pix_col_b + (pix_col.b as f32 * (ray.gamma)) as u64;
+    pub fn add_light(&mut self, light : Box<dyn Light>) {
+        self.lights.push(light);
+        self.light_tree = None;
+    }
+
+    pub fn set_environment(&mut self, environment : Option<EnvironmentMap>) {
+        self.environment = environment;
+        self.light_tree = None;
+    }
+
+    // for changes like the tint, which also change how much light the environment gives
+    pub fn environment_mut(&mut self) -> Option<&mut EnvironmentMap> {
+        self.light_tree = None;
+        self.environment.as_mut()
+    }
+
+    // focuses the camera on whatever is visible through the centre of pixel (x_idx, y_idx).
+    // returns the new focus distance, or None if the pixel sees nothing and focus is unchanged
+    pub fn autofocus(&mut self, x_idx : u16, y_idx : u16) -> Option<f32> {
+        let ray = self.camera.centre_ray(x_idx, y_idx)?;
+        let (t, _, _) = self.closest_camera_intersection(&ray)?;
+
+        // the focal plane is perpendicular to the view axis, so measure depth along it
+        let depth = t * ray.dir.dot(self.camera.forward());
+        self.camera.focus_distance = depth;
+        Some(depth)
+    }
+
+    // closest hit along the ray: its distance, the shape and which part of it, see
+    // RayTraceShape::intersect_part
+    pub fn closest_intersection(&self, ray : &Ray) -> Option<(f32, &dyn RayTraceShape, usize)> {
+        self.closest_intersection_where(ray, |_| true)
+    }
+
+    // closest hit for rays leaving the camera, which pass through shapes hidden from it
+    pub fn closest_camera_intersection(&self, ray : &Ray) -> Option<(f32, &dyn RayTraceShape, usize)> {
+        self.closest_intersection_where(ray, |shape| shape.material().visible_to_camera)
+    }
+
+    fn closest_intersection_where(&self, ray : &Ray, include : impl Fn(&dyn RayTraceShape) -> bool) -> Option<(f32, &dyn RayTraceShape, usize)> {
+        let mut closest_interx : Option<(f32, &dyn RayTraceShape, usize)> = None;
+        for shape in &self.geometry {
+            if !include(shape.as_ref()) {
+                continue;
+            }
+            if let Some((interx, part)) = shape.intersect_part(*ray) {
+                if closest_interx.is_none_or(|(closest, _, _)| closest > interx) {
+                    closest_interx = Some((interx, shape.as_ref(), part));
                 }
-                to_ret.push(Col3 {
-                    r : u64::min((pix_col_r / self.camera.rays_per_pixel as u64), 255) as u8,
-                    g : u64::min((pix_col_g / self.camera.rays_per_pixel as u64), 255) as u8,
-                    b : u64::min((pix_col_b / self.camera.rays_per_pixel as u64), 255) as u8,
-                });
             }
         }
-        to_ret
+        closest_interx
     }
-    
-    fn path_trace_ray(&self, ray : &mut Ray) -> Col3 {
-        let mut closest_interx : Option<(f32, &Box<dyn RayTraceShape>)> = None;
-        for shape in &self.geometry {
-            match shape.intersect(*ray) {
-                Some(interx) => {
-                    if closest_interx.is_none() || closest_interx.unwrap().0 > interx {
-                        closest_interx = Some((interx, shape));
-                    }
-                    
-                }
-                None => {
-                    
-                }
+
+    // true if nothing blocks the segment from origin along the unit vector dir up to distance
+    pub fn unoccluded(&self, origin : V3, dir : V3, distance : f32, time : f32) -> bool {
+        let mut ray = Ray::new(origin, dir, 0);
+        ray.time = time;
+        match self.closest_intersection(&ray) {
+            Some((t, _, _)) => t >= distance * (1.0 - 1e-4) - 1e-3,
+            None => true,
+        }
+    }
+
+    // medium the ray is in while inside volume, or outside all volumes for None
+    pub fn medium_in(&self, volume : Option<usize>) -> Option<&dyn Medium> {
+        match volume {
+            Some(idx) => Some(self.volumes[idx].medium.as_ref()),
+            None => self.medium.as_deref(),
+        }
+    }
+
+    // nearest volume boundary along the ray: its distance, which volume and whether the ray enters
+    // or leaves it there
+    pub fn next_volume_boundary(&self, ray : &Ray) -> Option<(f32, usize, bool)> {
+        let mut nearest : Option<(f32, usize, bool)> = None;
+        for (idx, volume) in self.volumes.iter().enumerate() {
+            let Some((t, part)) = volume.boundary.intersect_part(*ray) else { continue };
+            if nearest.is_none_or(|(closest, _, _)| t < closest) {
+                let normal = volume.boundary.part_normal(ray.origin + ray.dir * t, part, ray.time);
+                nearest = Some((t, idx, normal.dot(ray.dir) < 0.0));
             }
         }
+        nearest
+    }
 
-        match closest_interx {
-           
-            Some((interx, shape)) => {
-                if false && interx > self.camera.step_len * 5.0 {
-                    ray.origin = ray.origin + ray.dir * self.camera.step_len * 0.95;
-                    if ray.steps_remaining <= 0 {
-                        return Col3::black();
-                    }
-                    ray.steps_remaining = ray.steps_remaining - 1;
-                    return self.path_trace_ray(ray);
+    // volume around point, found by checking whether a ray from it leaves one first
+    pub fn volume_containing(&self, point : V3, time : f32) -> Option<usize> {
+        let mut ray = Ray::new(point, V3::new(0.36, 0.48, 0.8), 0);
+        ray.time = time;
+        match self.next_volume_boundary(&ray) {
+            Some((_, idx, false)) => Some(idx),
+            _ => None,
+        }
+    }
+
+    // share of light per channel that gets from origin along the unit vector dir up to distance,
+    // starting out inside volume. geometry in the way blocks everything, media dim what passes
+    pub fn transmittance(&self, origin : V3, dir : V3, distance : f32, time : f32, volume : Option<usize>, sampler : &mut dyn Sampler) -> V3 {
+        if !self.unoccluded(origin, dir, distance, time) {
+            return V3::zero();
+        }
+        let mut transmittance = V3::new(1.0, 1.0, 1.0);
+        let mut ray = Ray::new(origin, dir, 0);
+        ray.time = time;
+        let mut travelled = 0.0;
+        let mut volume = volume;
+        loop {
+            let crossing = self.next_volume_boundary(&ray);
+            let segment = match crossing {
+                Some((t, _, _)) => f32::min(t, distance - travelled),
+                None => distance - travelled,
+            };
+            if let Some(medium) = self.medium_in(volume) {
+                transmittance = transmittance.mul_elem(medium.transmittance(ray.origin, dir, segment, sampler));
+            }
+            match crossing {
+                Some((t, idx, entering)) if travelled + t < distance => {
+                    travelled += t;
+                    ray.origin += dir * t;
+                    volume = if entering { Some(idx) } else { None };
                 }
-                ray.steps_remaining = self.camera.max_steps;
-                shape.reflect(ray, ray.origin + ray.dir * interx);
-                if ray.bounces_remaining <= 0 {
-                    
-                    if ray.gamma > 0.0 {
-                        return ray.color;
-                    }
-                    else {
-                        return Col3::black();
-                    }
+                _ => return transmittance,
+            }
+        }
+    }
+
+    // emissive shapes come first, then the analytic lights and the environment last
+    fn light_count(&self) -> usize {
+        self.emitters.len() + self.lights.len() + self.environment.is_some() as usize
+    }
+
+    // builds the light tree over the current lights, which sample_light uses from then on. changing
+    // the geometry, lights or environment drops it, and until it is built again lights are picked
+    // uniformly
+    pub fn build_light_tree(&mut self) {
+        let mut bounds : Vec<_> = self.emitters.iter().map(|&idx| self.geometry[idx].light_bounds()).collect();
+        bounds.extend(self.lights.iter().map(|light| light.bounds()));
+        if let Some(environment) = &self.environment {
+            bounds.push(LightBounds::infinite(environment.irradiance()));
+        }
+        self.light_tree = Some(LightTree::new(&bounds));
+    }
+
+    // picks the index of a light for shading point with select in [0, 1), through the light tree
+    // if there is one and uniformly otherwise, along with the probability of having picked it
+    fn select_light(&self, point : V3, select : f32) -> Option<(usize, f32)> {
+        if let Some(tree) = &self.light_tree {
+            return tree.sample(point, select);
+        }
+        let count = self.light_count();
+        if count == 0 {
+            return None;
+        }
+        Some((usize::min((select * count as f32) as usize, count - 1), 1.0 / count as f32))
+    }
+
+    fn light_selection_pmf(&self, point : V3, idx : usize) -> f32 {
+        match &self.light_tree {
+            Some(tree) => tree.pmf(point, idx),
+            None => 1.0 / self.light_count() as f32,
+        }
+    }
+
+    // radiance arriving along a ray that missed all geometry
+    pub fn background(&self, dir : V3) -> V3 {
+        match &self.environment {
+            Some(environment) => environment.radiance(dir),
+            None => V3::zero(),
+        }
+    }
+
+    // density with which sample_light picks the environment in direction dir from point
+    pub fn environment_pdf(&self, point : V3, dir : V3) -> f32 {
+        match &self.environment {
+            Some(environment) => environment.pdf(dir) * self.light_selection_pmf(point, self.light_count() - 1),
+            None => 0.0,
+        }
+    }
+
+    // picks one light and samples a direction towards it from point. always draws the same three
+    // dimensions from the sampler, whether or not a light was found
+    pub fn sample_light(&self, point : V3, time : f32, sampler : &mut dyn Sampler) -> Option<LightSample> {
+        let select = sampler.get_1d();
+        let u = sampler.get_2d();
+        let (idx, selection_pdf) = self.select_light(point, select)?;
+
+        let emitter_count = self.emitters.len();
+        if idx >= emitter_count + self.lights.len() {
+            let environment = self.environment.as_ref()?;
+            let (dir, pdf) = environment.sample(u)?;
+            return Some(LightSample {
+                dir,
+                distance : f32::INFINITY,
+                radiance : environment.radiance(dir),
+                pdf : selection_pdf * pdf,
+                delta : false,
+            });
+        }
+        if idx >= emitter_count {
+            let mut sample = self.lights[idx - emitter_count].sample(point, u)?;
+            sample.pdf *= selection_pdf;
+            return Some(sample);
+        }
+        let shape = self.geometry[self.emitters[idx]].as_ref();
+        let (light_point, _, pdf) = shape.sample_towards(point, u, time)?;
+        let offset = light_point - point;
+        let distance = offset.magnitude();
+        if distance <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            dir : offset / distance,
+            distance,
+            radiance : shape.material().emission,
+            pdf : selection_pdf * pdf,
+            delta : false,
+        })
+    }
+
+    // density with which sample_light picks the direction from origin to point, a point on the
+    // emissive shape with the given normal
+    pub fn light_pdf(&self, origin : V3, point : V3, normal : V3, shape : &dyn RayTraceShape, time : f32) -> f32 {
+        let Some(&idx) = self.emitter_index.get(&shape_address(shape)) else { return 0.0 };
+        shape.pdf_towards(origin, point, normal, time) * self.light_selection_pmf(origin, idx)
+    }
+
+    pub fn render(&self) -> Vec<Col3> {
+        self.render_with(&self.camera)
+    }
+
+    // renders the scene as seen by some other camera, e.g. one eye of a stereo rig. bands of rows
+    // are traced in parallel, each into a film for the rows its samples reach, and merged in row
+    // order afterwards, so the image is the same on any number of threads
+    pub fn render_with(&self, camera : &Camera) -> Vec<Col3> {
+        let mut film = Film::new(camera.size_x, camera.size_y, camera.filter);
+        let bands : Vec<_> = (0..camera.size_y).step_by(BAND_ROWS as usize).collect();
+        let bands : Vec<_> = bands.into_par_iter().map(|first_row| {
+            let rows = first_row..u16::min(first_row.saturating_add(BAND_ROWS), camera.size_y);
+            let mut band = Film::band(camera.size_x, camera.size_y, camera.filter, film.rows_reached_from(rows.clone()));
+            let mut counts = Vec::with_capacity(camera.size_x as usize * rows.len());
+            for y in rows {
+                let mut sampler = camera.sampler.build(camera.rays_per_pixel as u32, camera.seed);
+                let integrator = camera.integrator.build();
+                counts.extend((0..camera.size_x).map(|x| self.render_pixel(camera, x, y, sampler.as_mut(), integrator.as_ref(), &mut band)));
+            }
+            (band, counts)
+        }).collect();
+
+        let mut sample_counts = Vec::with_capacity(camera.size_x as usize * camera.size_y as usize);
+        for (band, counts) in bands {
+            film.merge(&band);
+            sample_counts.extend(counts);
+        }
+        match camera.adaptive {
+            Some(adaptive) if adaptive.show_sample_counts => {
+                sample_counts.iter().map(|&count| sample_count_color(count, adaptive.max_samples)).collect()
+            }
+            _ => film.to_image(),
+        }
+    }
+
+    // traces the samples of pixel (x, y) and splats them onto film. returns how many samples the
+    // pixel took
+    fn render_pixel(&self, camera : &Camera, x : u16, y : u16, sampler : &mut dyn Sampler, integrator : &dyn Integrator, film : &mut Film) -> u32 {
+        let mut stats = PixelVariance::default();
+        let mut sample_index = 0;
+        loop {
+            let enough = match camera.adaptive {
+                // a pixel whose first batch gave no rays at all, like the corners outside a
+                // fisheye's circle, won't give any later either
+                Some(adaptive) if sample_index >= camera.rays_per_pixel as u32 => {
+                    sample_index >= adaptive.max_samples as u32 || stats.count == 0 || stats.converged(adaptive.threshold)
                 }
-                else {
-                    return self.path_trace_ray(ray);
+                _ => sample_index >= camera.rays_per_pixel as u32,
+            };
+            if enough {
+                return sample_index;
+            }
+            // each ray is traced right after it is generated, so its bounces continue
+            // with the dimensions that follow its own camera sample
+            let generated = camera.generate_ray(x, y, sample_index, sampler);
+            sample_index += 1;
+            let Some((mut ray, film_position)) = generated else { continue };
+            let radiance = integrator.radiance(self, camera, &mut ray, sampler);
+            stats.add(luminance(radiance));
+            film.add_sample(film_position, radiance);
+        }
+    }
+}
+
+// identifies a shape in the scene by where it lives, for looking up its light index
+fn shape_address(shape : &dyn RayTraceShape) -> usize {
+    shape as *const dyn RayTraceShape as *const () as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Projection, FisheyeMapping};
+    use crate::adaptive::AdaptiveSampling;
+    use crate::color::Col3;
+    use crate::material::Material;
+    use crate::quaternion::Quaternion;
+    use crate::sampler::{SamplerKind, mean_over_samples};
+    use crate::filter::Filter;
+    use crate::integrator::IntegratorKind;
+    use crate::sphere::Sphere;
+    use crate::plane::Plane;
+    use crate::quad::Quad;
+    use crate::light::PointLight;
+    use crate::environment::EnvironmentMap;
+    use crate::medium::HomogeneousMedium;
+    use crate::vector::{V2, V3};
+
+    fn test_scene(sampler : SamplerKind, seed : u32) -> Scene {
+        Scene::new(
+            vec![
+                Box::new(Sphere {
+                    origin : V3::new(0.0, 0.0, 3.0),
+                    radius : 1.0,
+                    material : Material::new(0.3, Col3::new(200, 120, 80)),
+                }),
+                Box::new(Sphere {
+                    origin : V3::new(0.0, -101.0, 3.0),
+                    radius : 100.0,
+                    material : Material::new(0.0, Col3::new(255, 255, 255)),
+                }),
+                Box::new(Sphere {
+                    origin : V3::new(1.5, 1.5, 2.0),
+                    radius : 0.3,
+                    material : Material::light(Col3::white(), 4.0),
+                }),
+            ],
+            vec![
+                Box::new(PointLight { position : V3::new(-1.0, 2.0, 1.0), intensity : V3::new(200.0, 200.0, 200.0) }),
+            ],
+            Camera {
+                location : V3::zero(),
+                orientation : Quaternion::identity(),
+                projection : Projection::Perspective,
+                fov_y : 1.2,
+                lens_shift : V2::zero(),
+                aperture_radius : 0.1,
+                focus_distance : 3.0,
+                aperture_blades : 0,
+                blade_rotation : 0.0,
+                shutter_open : 0.0,
+                shutter_close : 1.0,
+                size_x : 12,
+                size_y : 8,
+                rays_per_pixel : 4,
+                sampler,
+                seed,
+                filter : Filter::mitchell(),
+                adaptive : None,
+                integrator : IntegratorKind::Path,
+                bounce_depth : 3,
+                roulette_depth : 1,
+                max_steps : 1,
+                step_len : 1000.0,
+            },
+        )
+    }
+
+    fn same_image(a : &[Col3], b : &[Col3]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a.r, a.g, a.b) == (b.r, b.g, b.b))
+    }
+
+    #[test]
+    fn deep_paths_do_not_overflow_the_stack() {
+        // a ray bouncing between two parallel mirrors never escapes, so it runs the full depth
+        // mostly mirrors, with an occasional diffuse bounce that picks up the point light
+        let mirror = Material::new(0.9, Col3::white());
+        let mut scene = Scene::new(
+            vec![
+                Box::new(Plane { origin : V3::new(0.0, 0.0, 2.0), base_one : V3::new(1.0, 0.0, 0.0), base_two : V3::new(0.0, 1.0, 0.0), material : mirror }),
+                Box::new(Plane { origin : V3::new(0.0, 0.0, -2.0), base_one : V3::new(0.0, 1.0, 0.0), base_two : V3::new(1.0, 0.0, 0.0), material : mirror }),
+            ],
+            vec![Box::new(PointLight { position : V3::new(-1.0, 2.0, 1.0), intensity : V3::new(1.0, 1.0, 1.0) * 20000.0 })],
+            test_scene(SamplerKind::Sobol, 0).camera,
+        );
+        scene.camera.aperture_radius = 0.0;
+        scene.camera.size_x = 2;
+        scene.camera.size_y = 2;
+        scene.camera.rays_per_pixel = 1;
+        scene.camera.bounce_depth = 50000;
+        scene.camera.roulette_depth = 50000;
+        let image = scene.render();
+        assert!(image.iter().all(|c| c.r > 0));
+    }
+
+    #[test]
+    fn light_pdf_matches_sampling() {
+        // with and without the light tree
+        for tree in [false, true] {
+            let mut scene = test_scene(SamplerKind::Independent, 0);
+            if tree {
+                scene.build_light_tree();
+            }
+            let point = V3::new(0.0, -0.5, 0.0);
+            let mut sampler = SamplerKind::Independent.build(1, 3);
+            let mut checked = 0;
+            for i in 0..64 {
+                sampler.start_pixel_sample(0, 0, i);
+                let Some(sample) = scene.sample_light(point, 0.0, sampler.as_mut()) else { continue };
+                if sample.delta {
+                    continue;
                 }
+                let hit = point + sample.dir * sample.distance;
+                let emitter = scene.geometry[2].as_ref();
+                let pdf = scene.light_pdf(point, hit, emitter.normal_at(hit, 0.0), emitter, 0.0);
+                assert!(f32::abs(pdf - sample.pdf) < 1e-3 * sample.pdf);
+                checked += 1;
             }
-            None => {
-                return Col3::black();
+            assert!(checked > 0);
+        }
+    }
+
+    #[test]
+    fn stale_light_trees_are_not_used() {
+        let mut scene = test_scene(SamplerKind::Independent, 0);
+        let point = V3::new(0.0, -0.5, 0.0);
+        let assert_uniform = |scene : &Scene| {
+            for select in [0.1, 0.5, 0.9] {
+                let (idx, pmf) = scene.select_light(point, select).unwrap();
+                assert_eq!(pmf, 1.0 / 3.0);
+                assert_eq!(scene.light_selection_pmf(point, idx), pmf);
+            }
+        };
+        scene.build_light_tree();
+        scene.add_light(Box::new(PointLight { position : V3::new(1.0, 2.0, 1.0), intensity : V3::new(200.0, 200.0, 200.0) }));
+        assert_uniform(&scene);
+        // swapping the emissive sphere for another keeps the number of lights
+        scene.build_light_tree();
+        scene.set_geometry(vec![Box::new(Sphere { origin : V3::new(-1.5, 1.5, 2.0), radius : 0.5, material : Material::light(Col3::white(), 1.0) })]);
+        assert_uniform(&scene);
+    }
+
+    #[test]
+    fn hidden_lights_only_show_in_their_light() {
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        let panel = Quad {
+            origin : V3::new(-5.0, -5.0, 1.5),
+            base_one : V3::new(0.0, 10.0, 0.0),
+            base_two : V3::new(10.0, 0.0, 0.0),
+            material : Material::light(Col3::white(), 1.0),
+        };
+        scene.add_shape(Box::new(panel));
+        let (x, y) = (scene.camera.size_x / 2, scene.camera.size_y / 2);
+        let ray = scene.camera.centre_ray(x, y).unwrap();
+        let depth = |t : f32| t * ray.dir.z;
+        let (t, _, _) = scene.closest_camera_intersection(&ray).unwrap();
+        assert!(f32::abs(depth(t) - 1.5) < 1e-4);
+
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        scene.add_shape(Box::new(Quad { material : panel.material.hidden_from_camera(), ..panel }));
+        // the camera now sees the sphere behind the panel
+        let (t, _, _) = scene.closest_camera_intersection(&ray).unwrap();
+        assert!(depth(t) > 1.9);
+        // bounce and shadow rays still run into it
+        assert!(f32::abs(depth(scene.closest_intersection(&ray).unwrap().0) - 1.5) < 1e-4);
+    }
+
+    #[test]
+    fn escaping_rays_see_the_environment() {
+        let mut scene = Scene::new(Vec::new(), Vec::new(), test_scene(SamplerKind::Sobol, 0).camera);
+        scene.set_environment(Some(EnvironmentMap::from_fn(8, 4, |_| V3::new(0.5, 0.25, 0.0))));
+        for kind in [IntegratorKind::Path, IntegratorKind::Direct] {
+            scene.camera.integrator = kind;
+            for c in scene.render() {
+                assert_eq!((c.r, c.g, c.b), (127, 63, 0), "{:?}", kind);
             }
         }
     }
-}
 
+    #[test]
+    fn every_integrator_sees_the_scene() {
+        let mut kind = IntegratorKind::Path;
+        loop {
+            let mut scene = test_scene(SamplerKind::Sobol, 0);
+            scene.camera.integrator = kind;
+            let image = scene.render();
+            assert!(image.iter().any(|c| c.r > 0), "{:?}", kind);
+            kind = kind.next();
+            if kind == IntegratorKind::Path {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn equal_seeds_render_identical_images() {
+        for kind in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let first = test_scene(kind, 7).render();
+            let second = test_scene(kind, 7).render();
+            assert!(same_image(&first, &second), "{:?}", kind);
+            let reseeded = test_scene(kind, 8).render();
+            assert!(!same_image(&first, &reseeded), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn pixels_without_rays_stop_after_the_first_batch() {
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        scene.camera.projection = Projection::Fisheye { fov : std::f32::consts::PI, mapping : FisheyeMapping::Equisolid };
+        scene.camera.adaptive = Some(AdaptiveSampling::new(256, 0.0));
+        let camera = &scene.camera;
+        let mut sampler = camera.sampler.build(camera.rays_per_pixel as u32, camera.seed);
+        let integrator = camera.integrator.build();
+        let mut film = Film::new(camera.size_x, camera.size_y, camera.filter);
+        let corner = scene.render_pixel(camera, 0, 0, sampler.as_mut(), integrator.as_ref(), &mut film);
+        assert_eq!((corner, film.pixel(0, 0)), (camera.rays_per_pixel as u32, V3::zero()));
+        let centre = scene.render_pixel(camera, camera.size_x / 2, camera.size_y / 2, sampler.as_mut(), integrator.as_ref(), &mut film);
+        assert_eq!(centre, 256);
+    }
+
+    #[test]
+    fn rendering_uses_the_given_cameras_depth() {
+        let scene = test_scene(SamplerKind::Sobol, 0);
+        let mut shallow = scene.camera.clone();
+        shallow.bounce_depth = 0;
+        assert!(!same_image(&scene.render(), &scene.render_with(&shallow)));
+    }
+
+    #[test]
+    fn images_do_not_depend_on_the_thread_count() {
+        let render_on = |threads : usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            // tall enough for several bands, with the filter spilling samples across their edges
+            let mut scene = test_scene(SamplerKind::Sobol, 3);
+            scene.camera.size_y = 3 * BAND_ROWS + 5;
+            pool.install(|| scene.render())
+        };
+        let serial = render_on(1);
+        assert!(same_image(&serial, &render_on(4)));
+        assert!(same_image(&serial, &render_on(7)));
+    }
+
+    fn absorbing_ball() -> Volume {
+        Volume {
+            boundary : Box::new(Sphere { origin : V3::new(0.0, 0.0, 3.0), radius : 1.0, material : Material::new(0.0, Col3::black()) }),
+            medium : Box::new(HomogeneousMedium { absorption : V3::new(1.0, 1.0, 1.0), scattering : V3::zero(), g : 0.0 }),
+        }
+    }
+
+    #[test]
+    fn volumes_dim_light_passing_through() {
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        scene.set_geometry(Vec::new());
+        scene.volumes.push(absorbing_ball());
+        let mut sampler = SamplerKind::Sobol.build(1, 0);
+        let dir = V3::new(0.0, 0.0, 1.0);
+        // straight through the middle is two units of medium
+        let through = scene.transmittance(V3::zero(), dir, f32::INFINITY, 0.0, None, sampler.as_mut());
+        assert!(f32::abs(through.x - f32::exp(-2.0)) < 1e-3);
+        let from_inside = scene.transmittance(V3::new(0.0, 0.0, 3.0), dir, 5.0, 0.0, Some(0), sampler.as_mut());
+        assert!(f32::abs(from_inside.y - f32::exp(-1.0)) < 1e-3);
+        assert_eq!(scene.volume_containing(V3::new(0.2, 0.0, 3.0), 0.0), Some(0));
+        assert_eq!(scene.volume_containing(V3::zero(), 0.0), None);
+    }
+
+    #[test]
+    fn direct_lighting_is_dimmed_by_the_surrounding_volume() {
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        scene.set_geometry(vec![Box::new(Sphere { origin : V3::new(0.0, 0.0, 3.0), radius : 0.2, material : Material::new(0.0, Col3::white()) })]);
+        let mut sampler = SamplerKind::Sobol.build(1, 0);
+        let mut shade = |scene : &Scene, kind : IntegratorKind| {
+            sampler.start_pixel_sample(0, 0, 0);
+            let mut ray = Ray::new(V3::zero(), V3::new(0.0, 0.0, 1.0), 0);
+            kind.build().radiance(scene, &scene.camera, &mut ray, sampler.as_mut()).x
+        };
+        let clear = [shade(&scene, IntegratorKind::Direct), shade(&scene, IntegratorKind::Whitted)];
+        scene.volumes.push(absorbing_ball());
+        let dimmed = [shade(&scene, IntegratorKind::Direct), shade(&scene, IntegratorKind::Whitted)];
+        // the shadow ray to the point light crosses 0.862 units of the ball
+        for (clear, dimmed) in clear.iter().zip(dimmed) {
+            assert!(*clear > 0.0);
+            assert!(f32::abs(dimmed / clear - f32::exp(-0.862)) < 1e-2, "{} {}", clear, dimmed);
+        }
+    }
+
+    #[test]
+    fn paths_through_volumes_are_attenuated() {
+        let mut scene = Scene::new(Vec::new(), Vec::new(), test_scene(SamplerKind::Sobol, 0).camera);
+        scene.volumes.push(absorbing_ball());
+        scene.set_environment(Some(EnvironmentMap::from_fn(8, 4, |_| V3::new(1.0, 1.0, 1.0))));
+        let integrator = IntegratorKind::Path.build();
+        let total = mean_over_samples(4096, |sampler| {
+            let mut ray = Ray::new(V3::zero(), V3::new(0.0, 0.0, 1.0), 0);
+            integrator.radiance(&scene, &scene.camera, &mut ray, sampler).x
+        });
+        assert!(f32::abs(total - 255.0 * f32::exp(-2.0)) < 3.0, "{}", total);
+    }
+
+    #[test]
+    fn fog_scatters_light_into_the_view() {
+        let mut scene = test_scene(SamplerKind::Sobol, 0);
+        scene.set_geometry(Vec::new());
+        assert!(scene.render().iter().all(|c| c.r == 0));
+        scene.medium = Some(Box::new(HomogeneousMedium::fog(0.2, 0.5)));
+        assert!(scene.render().iter().any(|c| c.r > 0));
+    }
+}
@@ -1,7 +1,81 @@
+use std::f32::consts::PI;
 use crate::ray::Ray;
-use crate::vector::V3;
+use crate::vector::{V2, V3};
+use crate::material::Material;
+use crate::light_tree::LightBounds;
+use crate::adaptive::luminance;
 
-pub trait RayTraceShape {
+pub trait RayTraceShape : Send + Sync {
     fn intersect(&self, ray : Ray) -> Option<f32>;
-    fn reflect(&self, ray : &mut Ray, interx : V3);
+    // outward facing unit normal at a point on the surface, time matters for moving shapes only
+    fn normal_at(&self, point : V3, time : f32) -> V3;
+
+    // like intersect, along with which part of the shape the ray hit, e.g. a triangle of a mesh.
+    // shapes made of one piece report part 0
+    fn intersect_part(&self, ray : Ray) -> Option<(f32, usize)> {
+        self.intersect(ray).map(|t| (t, 0))
+    }
+
+    // normal at a point on the given part, so shapes don't have to find out again which part
+    // the point lies on
+    fn part_normal(&self, point : V3, _part : usize, time : f32) -> V3 {
+        self.normal_at(point, time)
+    }
+
+    fn material(&self) -> Material;
+    // surface area, infinite for unbounded shapes which can't be sampled as lights
+    fn area(&self) -> f32;
+    // uniformly distributed point on the surface for a sample in [0, 1)^2, with its outward normal
+    fn sample_point(&self, u : V2, time : f32) -> (V3, V3);
+
+    // point on the surface for lighting reference, with its normal and the solid angle density of
+    // having picked it as seen from reference. shapes that can sample the solid angle they cover
+    // directly override this, the rest pick uniformly by area
+    fn sample_towards(&self, reference : V3, u : V2, time : f32) -> Option<(V3, V3, f32)> {
+        area_sample_towards(self, reference, u, time)
+    }
+
+    // solid angle density with which sample_towards picks point, a point on the surface with the
+    // given normal
+    fn pdf_towards(&self, reference : V3, point : V3, normal : V3, _time : f32) -> f32 {
+        area_pdf_towards(self, reference, point, normal)
+    }
+
+    // extent and power of the shape as a light for the light tree. shapes that don't bound
+    // themselves are weighed by their power alone
+    fn light_bounds(&self) -> LightBounds {
+        LightBounds::unbounded(PI * self.area() * luminance(self.material().emission))
+    }
+}
+
+// uniform area sampling converted to solid angle. only the front side of the surface counts
+pub fn area_sample_towards<S : RayTraceShape + ?Sized>(shape : &S, reference : V3, u : V2, time : f32) -> Option<(V3, V3, f32)> {
+    let (point, normal) = shape.sample_point(u, time);
+    let pdf = area_pdf_towards(shape, reference, point, normal);
+    if pdf <= 0.0 {
+        return None;
+    }
+    Some((point, normal, pdf))
+}
+
+pub fn area_pdf_towards<S : RayTraceShape + ?Sized>(shape : &S, reference : V3, point : V3, normal : V3) -> f32 {
+    if !shape.area().is_finite() {
+        return 0.0;
+    }
+    area_to_solid_angle(1.0 / shape.area(), reference, point, normal)
+}
+
+// converts a density per unit of area at point, on a surface with the given normal, to a density
+// per solid angle as seen from reference
+pub fn area_to_solid_angle(area_pdf : f32, reference : V3, point : V3, normal : V3) -> f32 {
+    let offset = point - reference;
+    let distance_squared = offset.magnitude_squared();
+    if distance_squared <= 0.0 {
+        return 0.0;
+    }
+    let cos_light = -offset.normalized().dot(normal);
+    if cos_light <= 0.0 {
+        return 0.0;
+    }
+    area_pdf * distance_squared / cos_light
 }
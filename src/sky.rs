@@ -0,0 +1,174 @@
+use std::f32::consts::FRAC_PI_2;
+use crate::vector::V3;
+use crate::environment::EnvironmentMap;
+use crate::light::DirectionalLight;
+use crate::spectrum;
+
+// Analytic daylight from Preetham, Shirley and Smits 1999, "A Practical Analytic Model for
+// Daylight". The sky dome is baked into an EnvironmentMap so it gets importance sampled like any
+// HDR map, and the sun itself becomes a directional light of matching color that shines from a
+// disk of the sun's size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    pub sun_elevation : f32, // radians above the horizon
+    pub sun_azimuth : f32, // radians from +z towards +x
+    pub turbidity : f32, // haziness, 2 for a clear day up to about 10 for a hazy one
+    pub intensity : f32, // scales the sky, whose zenith is a few thousand cd/m^2 in the model
+    pub sun_intensity : f32, // irradiance of the sun before the atmosphere, 1.0 being white
+    pub sun_radius : f32, // angular radius of the sun's disk in radians
+}
+
+// coefficients of the Perez distribution for one of Y, x and y
+struct Perez {
+    a : f32,
+    b : f32,
+    c : f32,
+    d : f32,
+    e : f32,
+}
+
+impl Perez {
+    // theta is the angle of the view direction from the zenith, gamma its angle to the sun
+    fn evaluate(&self, theta : f32, gamma : f32) -> f32 {
+        let cos_gamma = f32::cos(gamma);
+        (1.0 + self.a * f32::exp(self.b / f32::max(f32::cos(theta), 0.01)))
+            * (1.0 + self.c * f32::exp(self.d * gamma) + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+impl Sky {
+    pub fn new(sun_elevation : f32, sun_azimuth : f32, turbidity : f32) -> Self {
+        Sky {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            intensity : 0.05,
+            sun_intensity : 4.0,
+            sun_radius : 0.00465,
+        }
+    }
+
+    // unit vector pointing at the sun
+    pub fn sun_direction(&self) -> V3 {
+        let (sin_e, cos_e) = f32::sin_cos(self.sun_elevation);
+        V3::new(cos_e * f32::sin(self.sun_azimuth), sin_e, cos_e * f32::cos(self.sun_azimuth))
+    }
+
+    fn perez(&self) -> [Perez; 3] {
+        let t = self.turbidity;
+        [
+            Perez { a : 0.1787 * t - 1.4630, b : -0.3554 * t + 0.4275, c : -0.0227 * t + 5.3251, d : 0.1206 * t - 2.5771, e : -0.0670 * t + 0.3703 },
+            Perez { a : -0.0193 * t - 0.2592, b : -0.0665 * t + 0.0008, c : -0.0004 * t + 0.2125, d : -0.0641 * t - 0.8989, e : -0.0033 * t + 0.0452 },
+            Perez { a : -0.0167 * t - 0.2608, b : -0.0950 * t + 0.0092, c : -0.0079 * t + 0.2102, d : -0.0441 * t - 1.6537, e : -0.0109 * t + 0.0529 },
+        ]
+    }
+
+    // luminance in kcd/m^2 and chromaticity x, y at the zenith
+    fn zenith(&self) -> [f32; 3] {
+        let t = self.turbidity;
+        let theta_s = FRAC_PI_2 - f32::max(self.sun_elevation, 0.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+        let cubic = |c : [f32; 4]| c[0] * theta_s * theta_s * theta_s + c[1] * theta_s * theta_s + c[2] * theta_s + c[3];
+        let x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+        [f32::max(luminance, 0.0), x, y]
+    }
+
+    // linear rgb of the sky in direction dir, 1.0 being white. below the horizon the sky is
+    // mirrored, which stands in for light bouncing off the ground
+    pub fn radiance(&self, dir : V3) -> V3 {
+        let dir = dir.normalized();
+        let up = V3::new(dir.x, f32::abs(dir.y), dir.z);
+        let theta = f32::acos(f32::clamp(up.y, 0.0, 1.0));
+        let gamma = f32::acos(f32::clamp(up.dot(self.sun_direction()), -1.0, 1.0));
+        let theta_s = FRAC_PI_2 - f32::max(self.sun_elevation, 0.0);
+
+        let zenith = self.zenith();
+        let mut yxy = [0.0; 3];
+        for (i, perez) in self.perez().iter().enumerate() {
+            yxy[i] = zenith[i] * perez.evaluate(theta, gamma) / perez.evaluate(0.0, theta_s);
+        }
+        let rgb = yxy_to_rgb(yxy[0], yxy[1], yxy[2]) * self.intensity;
+        let ground = if dir.y < 0.0 { 0.3 } else { 1.0 };
+        rgb.max_elem(V3::zero()) * ground
+    }
+
+    // share of sunlight per channel that makes it through the atmosphere. a rough fit with more
+    // blue scattered away the thicker the air and the hazier the sky, which reddens low suns
+    pub fn sun_transmittance(&self) -> V3 {
+        if self.sun_elevation <= 0.0 {
+            return V3::zero();
+        }
+        // relative air mass, Kasten and Young 1989
+        let zenith_degrees = 90.0 - self.sun_elevation.to_degrees();
+        let air_mass = 1.0 / (f32::cos(zenith_degrees.to_radians()) + 0.50572 * f32::powf(96.07995 - zenith_degrees, -1.6364));
+        let haze = 0.02 * self.turbidity;
+        let optical_depth = V3::new(0.05 + haze, 0.10 + haze, 0.22 + haze);
+        V3::new(f32::exp(-optical_depth.x * air_mass), f32::exp(-optical_depth.y * air_mass), f32::exp(-optical_depth.z * air_mass))
+    }
+
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight {
+            direction : -self.sun_direction(),
+            radiance : self.sun_transmittance() * (255.0 * self.sun_intensity),
+            angular_radius : self.sun_radius,
+        }
+    }
+
+    pub fn environment(&self, width : usize, height : usize) -> EnvironmentMap {
+        EnvironmentMap::from_fn(width, height, |dir| self.radiance(dir))
+    }
+}
+
+// CIE xyY to linear sRGB
+fn yxy_to_rgb(luminance : f32, x : f32, y : f32) -> V3 {
+    if y <= 0.0 {
+        return V3::zero();
+    }
+    spectrum::xyz_to_rgb(V3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::vector::V2;
+
+    #[test]
+    fn sky_is_brightest_around_the_sun_and_blue_overhead() {
+        let sky = Sky::new(0.6, 1.0, 3.0);
+        let sun = sky.sun_direction();
+        let away = V3::new(-sun.x, sun.y, -sun.z).normalized();
+        let near_sun = (sun + V3::new(0.0, 0.05, 0.0)).normalized();
+        assert!(sky.radiance(near_sun).y > sky.radiance(away).y);
+        let zenith = sky.radiance(V3::new(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x);
+    }
+
+    #[test]
+    fn low_suns_are_dimmer_and_redder() {
+        let noon = Sky::new(1.2, 0.0, 3.0).sun_transmittance();
+        let dusk = Sky::new(0.05, 0.0, 3.0).sun_transmittance();
+        assert!(dusk.y < noon.y);
+        assert!(dusk.x / dusk.z > noon.x / noon.z);
+        assert_eq!(Sky::new(-0.1, 0.0, 3.0).sun_transmittance(), V3::zero());
+    }
+
+    #[test]
+    fn sunlight_comes_from_the_sun_disk() {
+        let sky = Sky::new(0.6, 1.0, 3.0);
+        let sun = sky.sun();
+        let mut irradiance = V3::zero();
+        for i in 0..16 {
+            let sample = sun.sample(V3::zero(), V2::new((i as f32 + 0.5) / 16.0, (i * 7 % 16) as f32 / 16.0)).unwrap();
+            assert!(sample.dir.dot(sky.sun_direction()) >= f32::cos(sky.sun_radius) - 1e-6);
+            irradiance += sample.radiance / sample.pdf / 16.0;
+        }
+        assert!((irradiance - sun.radiance).magnitude() < 1e-2 * sun.radiance.magnitude());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::vector::V3;
+use crate::adaptive::luminance;
+
+// Colors of hot bodies, for giving lamps a temperature in Kelvin instead of an rgb triple. The
+// spectrum is integrated against the CIE 1931 observer and converted to linear sRGB, the space
+// all radiance in the renderer is in.
+
+// CIE 1931 color matching functions as fitted by Wyman, Sloan and Shirley 2013, "Simple Analytic
+// Approximations to the CIE XYZ Color Matching Functions". wavelength in nm
+fn color_matching(wavelength : f64) -> [f64; 3] {
+    let g = |mean : f64, below : f64, above : f64| {
+        let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+        f64::exp(-0.5 * t * t)
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+// spectral radiance of a blackbody by Planck's law, wavelength in nm, in W / (sr m^2 m)
+fn planck(wavelength : f64, kelvin : f64) -> f64 {
+    const C : f64 = 299792458.0;
+    const H : f64 = 6.62606957e-34;
+    const K_B : f64 = 1.3806488e-23;
+    let l = wavelength * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * (f64::exp(H * C / (l * K_B * kelvin)) - 1.0))
+}
+
+pub fn xyz_to_rgb(xyz : V3) -> V3 {
+    V3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+// linear rgb of a blackbody at kelvin, with the spectrum scaled to 1 at its peak so temperatures
+// compare by how much of their light is visible: a 1500K candle flame comes out far dimmer than
+// 6500K daylight. none of the primaries go negative, colors outside sRGB are clipped
+pub fn blackbody(kelvin : f32) -> V3 {
+    if kelvin <= 0.0 {
+        return V3::zero();
+    }
+    let kelvin = kelvin as f64;
+    // Wien's displacement law
+    let peak = planck(2.8977721e-3 / kelvin * 1e9, kelvin);
+    let mut xyz = [0.0; 3];
+    let mut y_total = 0.0;
+    for step in 0..=94 {
+        let wavelength = 360.0 + 5.0 * step as f64;
+        let radiance = planck(wavelength, kelvin) / peak;
+        let cmf = color_matching(wavelength);
+        for (sum, weight) in xyz.iter_mut().zip(cmf) {
+            *sum += radiance * weight;
+        }
+        y_total += cmf[1];
+    }
+    let xyz = V3::new((xyz[0] / y_total) as f32, (xyz[1] / y_total) as f32, (xyz[2] / y_total) as f32);
+    xyz_to_rgb(xyz).max_elem(V3::zero())
+}
+
+// blackbody color scaled to a luminance of 1, so the temperature only changes the hue and
+// brightness stays with whatever the color is multiplied by
+pub fn blackbody_normalized(kelvin : f32) -> V3 {
+    let rgb = blackbody(kelvin);
+    let y = luminance(rgb);
+    if y <= 0.0 { V3::zero() } else { rgb / y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperatures_go_from_red_to_blue() {
+        let candle = blackbody_normalized(1900.0);
+        let daylight = blackbody_normalized(6500.0);
+        let sky = blackbody_normalized(12000.0);
+        assert!(candle.x > candle.y && candle.y > candle.z);
+        assert!(f32::abs(daylight.x / daylight.z - 1.0) < 0.15);
+        assert!(sky.z > sky.x);
+        assert!(f32::abs(luminance(candle) - 1.0) < 1e-5);
+    }
+
+    #[test]
+    fn hotter_bodies_show_more_visible_light() {
+        assert!(luminance(blackbody(1500.0)) < luminance(blackbody(3000.0)));
+        assert!(luminance(blackbody(3000.0)) < luminance(blackbody(5500.0)));
+        assert_eq!(blackbody(0.0), V3::zero());
+    }
+}
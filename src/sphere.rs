@@ -1,10 +1,12 @@
-use crate::vector::V3;
+use std::f32::consts::PI;
+use crate::vector::{V2, V3};
 use crate::ray::Ray;
-use crate::shape::RayTraceShape;
-use crate::color::Col3;
+use crate::shape::{RayTraceShape, area_sample_towards, area_pdf_towards};
+use crate::sampling;
 use crate::material::Material;
+use crate::light_tree::LightBounds;
 
-// A plane spanned by two basis vectors, offset by an origin vector.
+// A sphere around an origin vector.
 #[derive(Clone, Copy)]
 pub struct Sphere {
     pub origin : V3,
@@ -22,70 +24,87 @@ impl Sphere {
 
 impl RayTraceShape for Sphere {
 
-    // My implementation that produces the overlap bug 
+    // nearest root of |o + t d - c|^2 = r^2 in front of the ray. t is in units of ray.dir, so hit
+    // points are ray.origin + ray.dir * t for unnormalized directions as well
     fn intersect(&self, ray : Ray) -> Option<f32> {
-        let closest_to_sph_center = ray.dir.project(self.origin - ray.origin);
-        let dist_closest_to_center = (closest_to_sph_center + ray.origin).dist(self.origin);
-        
-        if dist_closest_to_center > self.radius {
+        let oc = ray.origin - self.origin;
+        let a = ray.dir.magnitude_squared();
+        let half_b = oc.dot(ray.dir);
+        let c = oc.magnitude_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
             return None;
         }
+        let sqrt_d = f32::sqrt(discriminant);
+        let near = (-half_b - sqrt_d) / a;
+        if near > 0.001 {
+            return Some(near);
+        }
+        let far = (-half_b + sqrt_d) / a;
+        if far > 0.001 {
+            Some(far)
+        }
         else {
-            // ray intersects sphere
-            let mut flag = false;
-            if self.radius*self.radius - dist_closest_to_center*dist_closest_to_center < 0.01 {
-                return None;
-            }
-            let dist_to_interx = (f64::sqrt( (self.radius as f64 * self.radius as f64 - dist_closest_to_center as f64 * dist_closest_to_center as f64)) as f64) as f32;
-            if dist_to_interx < 0.001 {
-                return None;
-            }
-            if flag {
-                println!("dist to interx {}", dist_to_interx);
-            }
-            //if true || (ray.origin + ray.dir * dist_to_interx).magnitude() < (ray.origin + ray.dir * -dist_to_interx).magnitude() {
-                return Some(f32::abs(dist_to_interx));
-            //}
-            //else {
-            //    return Some(ray.origin + ray.dir * dist_to_interx);
-                //return Some(ray.origin - ray.dir * dist_to_interx);
-            //}
+            None
         }
+    }
 
-    
+
+    fn normal_at(&self, point : V3, _time : f32) -> V3 {
+        self.normal(point)
     }
-    // v Raytracing in a weekend's implementation v
-    /*
-    
-    fn intersect(&self, r : Ray) -> Option<f32> {
-        let oc : V3 = r.origin - self.origin;
-        let a = r.dir.magnitude() * r.dir.magnitude();
-        let half_b = oc.dot(r.dir);
-        let c = oc.magnitude() * oc.magnitude() - self.radius*self.radius;
-        let discriminant = half_b*half_b - a*c;
 
-        if (discriminant < 0.001) {
-            return None;
-        } else {
-            let sqrtd = f32::sqrt(discriminant);
-            let mut root = (-half_b - sqrtd) / a;
-            if (root < 0.0 || 9999.0 < root) {
-                root = (-half_b + sqrtd) / a;
-                if (root < 0.0 || 9999.0 < root) {
-                    return None; }
-            }
-            return Some(root);
+    fn material(&self) -> Material {
+        self.material
+    }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_point(&self, u : V2, _time : f32) -> (V3, V3) {
+        let z = 1.0 - 2.0 * u.x;
+        let r = f32::sqrt(f32::max(0.0, 1.0 - z * z));
+        let phi = 2.0 * PI * u.y;
+        let normal = V3::new(r * f32::cos(phi), r * f32::sin(phi), z);
+        (self.origin + normal * self.radius, normal)
+    }
+
+    // from outside, samples the cone of directions the sphere covers (pbrt's approach). every
+    // sample then lands on the visible cap, unlike area sampling where half of them face away
+    fn sample_towards(&self, reference : V3, u : V2, time : f32) -> Option<(V3, V3, f32)> {
+        let offset = self.origin - reference;
+        let distance_squared = offset.magnitude_squared();
+        if distance_squared <= self.radius * self.radius {
+            return area_sample_towards(self, reference, u, time);
+        }
+        let distance = f32::sqrt(distance_squared);
+        let sin_max_squared = self.radius * self.radius / distance_squared;
+        let cos_max = f32::sqrt(f32::max(0.0, 1.0 - sin_max_squared));
+
+        let cos_theta = 1.0 - u.x * (1.0 - cos_max);
+        let sin_theta_squared = f32::max(0.0, 1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * PI * u.y;
+        let local = V3::new(f32::sqrt(sin_theta_squared) * f32::cos(phi), f32::sqrt(sin_theta_squared) * f32::sin(phi), cos_theta);
+        let dir = sampling::to_world(local, offset / distance).normalized();
+
+        // nearest intersection of the sampled direction with the sphere
+        let along = distance * cos_theta - f32::sqrt(f32::max(0.0, self.radius * self.radius - distance_squared * sin_theta_squared));
+        let point = reference + dir * along;
+        Some((point, self.normal(point), 1.0 / (2.0 * PI * (1.0 - cos_max))))
+    }
+
+    fn pdf_towards(&self, reference : V3, point : V3, normal : V3, _time : f32) -> f32 {
+        let distance_squared = (self.origin - reference).magnitude_squared();
+        if distance_squared <= self.radius * self.radius {
+            return area_pdf_towards(self, reference, point, normal);
         }
+        let cos_max = f32::sqrt(f32::max(0.0, 1.0 - self.radius * self.radius / distance_squared));
+        1.0 / (2.0 * PI * (1.0 - cos_max))
     }
-    */  
-    
-    fn reflect(&self, ray : &mut Ray, interx : V3) {
-        let proj_n_raydir = (V3::zero() - self.normal(interx)).project(ray.dir);
-        let new_dir = V3::zero() - ((2.0 * proj_n_raydir) - ray.dir);
-        
-        ray.dir = new_dir;
-        ray.origin = interx;
-        self.material.shade_ray_reflection(ray, self.normal(interx));
-        ray.bounces_remaining = ray.bounces_remaining - 1;
+
+    fn light_bounds(&self) -> LightBounds {
+        let extent = V3::new(self.radius, self.radius, self.radius);
+        LightBounds::surface(self.origin - extent, self.origin + extent, V3::new(0.0, 1.0, 0.0), -1.0, self.material.emission, self.area())
     }
 }
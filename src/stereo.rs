@@ -0,0 +1,209 @@
+use crate::vector::V2;
+use crate::color::Col3;
+use crate::camera::{Camera, Projection};
+use crate::scene::Scene;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+// How the two eyes of a perspective rig are aimed at the convergence distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoMode {
+    // each eye is rotated to look at the convergence point. simple, but introduces vertical
+    // parallax towards the image corners
+    ToeIn,
+    // parallel view axes with the image shifted sideways, the usual choice for comfortable stereo
+    OffAxis,
+    // omnidirectional stereo equirectangular panorama for VR, the base camera projection is ignored
+    Omnidirectional,
+}
+
+// How the left and right images are packed into one output image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoLayout {
+    // left eye on the left half, twice as wide as one eye
+    SideBySide,
+    // left eye on the top half, twice as tall as one eye
+    OverUnder,
+    // red channel from the left eye, green and blue from the right, the size of one eye
+    Anaglyph,
+}
+
+impl StereoMode {
+    // parses the names accepted on the command line
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "toe-in" => Some(StereoMode::ToeIn),
+            "off-axis" => Some(StereoMode::OffAxis),
+            "omni" => Some(StereoMode::Omnidirectional),
+            _ => None,
+        }
+    }
+}
+
+impl StereoLayout {
+    // parses the names accepted on the command line
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name {
+            "side-by-side" => Some(StereoLayout::SideBySide),
+            "over-under" => Some(StereoLayout::OverUnder),
+            "anaglyph" => Some(StereoLayout::Anaglyph),
+            _ => None,
+        }
+    }
+}
+
+// Renders a scene twice from two eyes around the scene camera.
+#[derive(Clone, Copy, Debug)]
+pub struct StereoRig {
+    pub ipd : f32, // interpupillary distance in scene units
+    pub convergence : f32, // distance of the zero parallax plane in front of the camera
+    pub mode : StereoMode,
+    pub layout : StereoLayout,
+}
+
+impl StereoRig {
+    // the camera for one eye, derived from the centre camera
+    pub fn eye_camera(&self, camera : &Camera, eye : Eye) -> Camera {
+        let side = match eye {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        };
+        let offset = side * 0.5 * self.ipd;
+        let mut eye_camera = camera.clone();
+        match self.mode {
+            StereoMode::ToeIn => {
+                let target = camera.location + camera.forward() * self.convergence;
+                let eye_location = camera.location + camera.right() * offset;
+                eye_camera.look_at(eye_location, target, camera.up());
+            }
+            StereoMode::OffAxis => {
+                // shift the image so the centre of the convergence plane stays in the middle
+                eye_camera.location = camera.location + camera.right() * offset;
+                eye_camera.lens_shift = camera.lens_shift + V2::new(-offset / self.convergence, 0.0);
+            }
+            StereoMode::Omnidirectional => {
+                eye_camera.projection = Projection::OmniStereo { eye_offset : offset };
+            }
+        }
+        eye_camera
+    }
+
+    // size of the packed output image for a camera of size_x by size_y
+    pub fn output_size(&self, camera : &Camera) -> (u32, u32) {
+        let (w, h) = (camera.size_x as u32, camera.size_y as u32);
+        match self.layout {
+            StereoLayout::SideBySide => (2 * w, h),
+            StereoLayout::OverUnder => (w, 2 * h),
+            StereoLayout::Anaglyph => (w, h),
+        }
+    }
+
+    // renders both eyes from the scene camera and packs them according to the layout, row major
+    // with output_size dimensions
+    pub fn render(&self, scene : &Scene) -> Vec<Col3> {
+        let left = scene.render_with(&self.eye_camera(&scene.camera, Eye::Left));
+        let right = scene.render_with(&self.eye_camera(&scene.camera, Eye::Right));
+        self.pack(&left, &right, scene.camera.size_x as usize)
+    }
+
+    pub fn pack(&self, left : &[Col3], right : &[Col3], width : usize) -> Vec<Col3> {
+        match self.layout {
+            StereoLayout::SideBySide => left
+                .chunks(width)
+                .zip(right.chunks(width))
+                .flat_map(|(l, r)| l.iter().chain(r.iter()).copied())
+                .collect(),
+            StereoLayout::OverUnder => left.iter().chain(right.iter()).copied().collect(),
+            StereoLayout::Anaglyph => left
+                .iter()
+                .zip(right.iter())
+                .map(|(l, r)| Col3::new(l.r, r.g, r.b))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quaternion::Quaternion;
+    use crate::camera::CameraSample;
+    use crate::sampler::SamplerKind;
+    use crate::filter::Filter;
+    use crate::integrator::IntegratorKind;
+    use crate::vector::V3;
+
+    fn test_camera() -> Camera {
+        Camera {
+            location : V3::new(1.0, 0.0, 0.0),
+            orientation : Quaternion::identity(),
+            projection : Projection::Perspective,
+            fov_y : 1.0,
+            lens_shift : V2::zero(),
+            aperture_radius : 0.0,
+            focus_distance : 1.0,
+            aperture_blades : 0,
+            blade_rotation : 0.0,
+            shutter_open : 0.0,
+            shutter_close : 0.0,
+            size_x : 4,
+            size_y : 4,
+            rays_per_pixel : 1,
+            sampler : SamplerKind::Independent,
+            seed : 0,
+            filter : Filter::pixel_box(),
+            adaptive : None,
+            integrator : IntegratorKind::Path,
+            bounce_depth : 1,
+            roulette_depth : 1,
+            max_steps : 1,
+            step_len : 1000.0,
+        }
+    }
+
+    #[test]
+    fn eyes_converge_on_the_convergence_point() {
+        let camera = test_camera();
+        let convergence_point = camera.location + V3::new(0.0, 0.0, 5.0);
+        for mode in [StereoMode::ToeIn, StereoMode::OffAxis] {
+            let rig = StereoRig { ipd : 0.064, convergence : 5.0, mode, layout : StereoLayout::SideBySide };
+            for eye in [Eye::Left, Eye::Right] {
+                let eye_camera = rig.eye_camera(&camera, eye);
+                // the centre of the image looks at the convergence point
+                let ray = eye_camera.pixel_ray(2, 2, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap();
+                let to_point = (convergence_point - ray.origin).normalized();
+                assert!((ray.dir - to_point).magnitude() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn omnidirectional_eyes_sit_on_a_circle() {
+        let camera = test_camera();
+        let rig = StereoRig { ipd : 0.064, convergence : 5.0, mode : StereoMode::Omnidirectional, layout : StereoLayout::OverUnder };
+        let right = rig.eye_camera(&camera, Eye::Right);
+        let ray = right.pixel_ray(3, 2, CameraSample::new(V2::zero(), V2::zero(), 0.0)).unwrap();
+        // looking along +x, the right eye is offset backwards along -z
+        assert!((ray.dir - V3::new(1.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert!((ray.origin - camera.location - V3::new(0.0, 0.0, -0.032)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn layouts_pack_both_eyes() {
+        let (l, r) = (Col3::new(10, 20, 30), Col3::new(40, 50, 60));
+        let left = vec![l; 4];
+        let right = vec![r; 4];
+        let rig = |layout| StereoRig { ipd : 0.064, convergence : 5.0, mode : StereoMode::OffAxis, layout };
+        let side_by_side = rig(StereoLayout::SideBySide).pack(&left, &right, 2);
+        assert_eq!(side_by_side.iter().map(|c| c.r).collect::<Vec<_>>(), vec![10, 10, 40, 40, 10, 10, 40, 40]);
+        let over_under = rig(StereoLayout::OverUnder).pack(&left, &right, 2);
+        assert_eq!(over_under[3].r, 10);
+        assert_eq!(over_under[4].r, 40);
+        let anaglyph = rig(StereoLayout::Anaglyph).pack(&left, &right, 2);
+        assert_eq!((anaglyph[0].r, anaglyph[0].g, anaglyph[0].b), (10, 50, 60));
+    }
+}
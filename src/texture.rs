@@ -1,4 +1,4 @@
-use crate::color::{Col3, Col4};
+use crate::color::Col3;
 
 pub struct Texture {
     
@@ -0,0 +1,237 @@
+use crate::vector::V3;
+use crate::matrix::Matrix4;
+use crate::quaternion::Quaternion;
+use crate::ray::Ray;
+
+// An affine transform from object space to world space. The inverse is kept alongside the
+// matrix so that rays can be brought into object space without inverting on every hit.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    pub matrix : Matrix4,
+    pub inverse : Matrix4,
+}
+
+impl Transform {
+    #[allow(dead_code)]
+    pub fn identity() -> Self {
+        Self {
+            matrix : Matrix4::identity(),
+            inverse : Matrix4::identity(),
+        }
+    }
+
+    // None if the matrix is singular, e.g. a scale of zero along some axis
+    #[allow(dead_code)]
+    pub fn from_matrix(matrix : Matrix4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        Some(Self { matrix, inverse })
+    }
+
+    pub fn translation(offset : V3) -> Self {
+        Self {
+            matrix : Matrix4::translation(offset),
+            inverse : Matrix4::translation(-offset),
+        }
+    }
+
+    // non uniform scale, every factor has to be non zero
+    pub fn scaling(factors : V3) -> Self {
+        Self {
+            matrix : Matrix4::scaling(factors),
+            inverse : Matrix4::scaling(V3::new(1.0 / factors.x, 1.0 / factors.y, 1.0 / factors.z)),
+        }
+    }
+
+    // rotations are orthogonal, so the inverse is the transpose
+    #[allow(dead_code)]
+    pub fn rotation_x(theta : f32) -> Self {
+        let matrix = Matrix4::rotation_x(theta);
+        Self { matrix, inverse : matrix.transpose() }
+    }
+    pub fn rotation_y(theta : f32) -> Self {
+        let matrix = Matrix4::rotation_y(theta);
+        Self { matrix, inverse : matrix.transpose() }
+    }
+    #[allow(dead_code)]
+    pub fn rotation_z(theta : f32) -> Self {
+        let matrix = Matrix4::rotation_z(theta);
+        Self { matrix, inverse : matrix.transpose() }
+    }
+
+    pub fn rotation(q : Quaternion) -> Self {
+        let matrix = Matrix4::from_matrix3(&q.to_matrix3());
+        Self { matrix, inverse : matrix.transpose() }
+    }
+
+    // apply self first, then next. scale -> rotate -> translate reads left to right:
+    // Transform::scaling(s).then(&Transform::rotation_y(a)).then(&Transform::translation(t))
+    pub fn then(&self, next : &Transform) -> Self {
+        Self {
+            matrix : next.matrix * self.matrix,
+            inverse : self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverted(&self) -> Self {
+        Self {
+            matrix : self.inverse,
+            inverse : self.matrix,
+        }
+    }
+
+    // normals transform with the inverse transpose so they stay perpendicular under non uniform scale
+    pub fn normal_matrix(&self) -> Matrix4 {
+        self.inverse.transpose()
+    }
+
+    pub fn transform_point(&self, p : V3) -> V3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn transform_vec3(&self, v : V3) -> V3 {
+        self.matrix.transform_vec3(v)
+    }
+
+    // returns an unnormalized normal, callers normalize after transforming
+    pub fn transform_normal(&self, n : V3) -> V3 {
+        self.normal_matrix().transform_vec3(n)
+    }
+
+    // the direction is not renormalized, so a distance t along the result matches t along the input
+    pub fn transform_ray(&self, ray : &Ray) -> Ray {
+        let mut out = *ray;
+        out.origin = self.transform_point(ray.origin);
+        out.dir = self.transform_vec3(ray.dir);
+        out
+    }
+}
+
+// One pose of an animated object, applied as scale, then rotation, then translation.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time : f32,
+    pub translation : V3,
+    pub rotation : Quaternion,
+    pub scale : V3,
+}
+
+impl Keyframe {
+    pub fn new(time : f32, translation : V3, rotation : Quaternion, scale : V3) -> Self {
+        Keyframe {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        Transform::scaling(self.scale)
+            .then(&Transform::rotation(self.rotation))
+            .then(&Transform::translation(self.translation))
+    }
+
+    // translation and scale are interpolated linearly, rotation along the shortest arc
+    pub fn interpolate(&self, to : &Keyframe, t : f32) -> Keyframe {
+        Keyframe {
+            time : self.time + (to.time - self.time) * t,
+            translation : self.translation.lerp(to.translation, t),
+            rotation : self.rotation.slerp(to.rotation, t),
+            scale : self.scale.lerp(to.scale, t),
+        }
+    }
+}
+
+// Transform that moves through a list of keyframes sorted by time. Before the first and after the
+// last keyframe the object holds still.
+#[derive(Clone, Debug)]
+pub struct AnimatedTransform {
+    pub keyframes : Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    pub fn new(mut keyframes : Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "an animated transform needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        AnimatedTransform { keyframes }
+    }
+
+    pub fn pose_at(&self, time : f32) -> Keyframe {
+        let first = self.keyframes[0];
+        if time <= first.time {
+            return first;
+        }
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if time <= b.time {
+                let span = b.time - a.time;
+                let t = if span > 0.0 { (time - a.time) / span } else { 1.0 };
+                return a.interpolate(&b, t);
+            }
+        }
+        *self.keyframes.last().unwrap()
+    }
+
+    pub fn transform_at(&self, time : f32) -> Transform {
+        self.pose_at(time).to_transform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn close(a : V3, b : V3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn then_applies_left_to_right() {
+        let transform = Transform::scaling(V3::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotation_z(PI * 0.5))
+            .then(&Transform::translation(V3::new(0.0, 0.0, 3.0)));
+        // scaled to (2, 0, 0), turned onto the y axis, then moved along z
+        let p = V3::new(1.0, 0.0, 0.0);
+        let moved = transform.transform_point(p);
+        assert!(close(moved, V3::new(0.0, 2.0, 3.0)));
+        assert!(close(transform.inverted().transform_point(moved), p));
+        // directions ignore the translation
+        assert!(close(transform.transform_vec3(p), V3::new(0.0, 2.0, 0.0)));
+        let composed = Transform::from_matrix(transform.matrix).unwrap();
+        assert!(close(composed.inverse.transform_point(moved), p));
+        assert!(Transform::from_matrix(Matrix4::scaling(V3::new(1.0, 0.0, 1.0))).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::scaling(V3::new(2.0, 1.0, 1.0)).then(&Transform::rotation_x(0.3));
+        let normal = V3::new(1.0, 1.0, 0.0).normalized();
+        let tangent = V3::new(1.0, -1.0, 0.0);
+        let moved_tangent = transform.transform_vec3(tangent);
+        assert!(f32::abs(transform.transform_normal(normal).dot(moved_tangent)) < 1e-5);
+        // transforming the normal like a direction would tilt it off the surface
+        assert!(f32::abs(transform.transform_vec3(normal).dot(moved_tangent)) > 0.5);
+        // for rotations the normal matrix is the rotation itself
+        let rotation = Transform::rotation_y(0.8);
+        assert!(close(rotation.normal_matrix().transform_vec3(normal), rotation.transform_vec3(normal)));
+        assert!(close(Transform::identity().transform_normal(normal), normal));
+    }
+
+    #[test]
+    fn animation_interpolates_between_keyframes() {
+        let up = V3::new(0.0, 1.0, 0.0);
+        let animation = AnimatedTransform::new(vec![
+            Keyframe::new(1.0, V3::new(2.0, 0.0, 0.0), Quaternion::from_axis_angle(up, PI * 0.5), V3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(0.0, V3::zero(), Quaternion::identity(), V3::new(1.0, 1.0, 1.0)),
+        ]);
+        let p = V3::new(1.0, 0.0, 0.0);
+        // halfway the point has turned 45 degrees and moved half of the translation
+        let mid = animation.transform_at(0.5).transform_point(p);
+        let expected = V3::new(f32::sqrt(0.5) + 1.0, 0.0, -f32::sqrt(0.5));
+        assert!((mid - expected).magnitude() < 1e-5);
+        // outside the keyframes the pose is clamped
+        let after = animation.transform_at(3.0).transform_point(p);
+        assert!((after - V3::new(2.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+}
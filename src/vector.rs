@@ -1,6 +1,6 @@
 use std::ops;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct V3 {
     pub x : f32,
     pub y : f32,
@@ -24,12 +24,16 @@ impl V3 {
         V3 {
             x : self.y * rh.z - self.z * rh.y,
             y : -(self.x * rh.z - self.z * rh.x),
-            z : self.x * rh.y - self.y - rh.x
+            z : self.x * rh.y - self.y * rh.x,
         }
     }
     
     pub fn magnitude(&self) -> f32 {
-        f32::sqrt(self.x * self.x + self.y * self.y + self.z * self.z)
+        f32::sqrt(self.magnitude_squared())
+    }
+
+    pub fn magnitude_squared(&self) -> f32 {
+        self.dot(*self)
     }
 
     pub fn dist(&self, other : V3) -> f32 {
@@ -47,11 +51,60 @@ impl V3 {
         let mag = self.magnitude();
         let denominator = mag*mag;
         if denominator < 0.0001 {
-            return V3::zero();
+            V3::zero()
         }
         else {
-            return (numerator/denominator) * *self;
+            (numerator/denominator) * *self
+        }
+    }
+
+    // componentwise product
+    pub fn mul_elem(&self, rh : V3) -> V3 {
+        V3::new(self.x * rh.x, self.y * rh.y, self.z * rh.z)
+    }
+
+    pub fn lerp(&self, to : V3, t : f32) -> V3 {
+        *self + (to - *self) * t
+    }
+
+    pub fn min_elem(&self, rh : V3) -> V3 {
+        V3::new(f32::min(self.x, rh.x), f32::min(self.y, rh.y), f32::min(self.z, rh.z))
+    }
+
+    pub fn max_elem(&self, rh : V3) -> V3 {
+        V3::new(f32::max(self.x, rh.x), f32::max(self.y, rh.y), f32::max(self.z, rh.z))
+    }
+
+    pub fn max_component(&self) -> f32 {
+        f32::max(self.x, f32::max(self.y, self.z))
+    }
+
+    // mirror self (pointing towards the surface) about the unit normal n
+    pub fn reflect(&self, n : V3) -> V3 {
+        *self - 2.0 * self.dot(n) * n
+    }
+
+    // snell refraction of the unit vector self through the unit normal n, where eta is the ratio
+    // of refractive indices outside / inside. None on total internal reflection
+    pub fn refract(&self, n : V3, eta : f32) -> Option<V3> {
+        let cos_i = -self.dot(n);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return None;
         }
+        let cos_t = f32::sqrt(1.0 - sin2_t);
+        Some(eta * *self + (eta * cos_i - cos_t) * n)
+    }
+
+    // two unit vectors that together with the unit vector self form a right handed orthonormal
+    // basis (Duff et al. 2017, "Building an Orthonormal Basis, Revisited")
+    pub fn orthonormal_basis(&self) -> (V3, V3) {
+        let sign = f32::copysign(1.0, self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        let tangent = V3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x);
+        let bitangent = V3::new(b, sign + self.y * self.y * a, -self.y);
+        (tangent, bitangent)
     }
 }
 
@@ -96,4 +149,247 @@ impl ops::Mul<V3> for f32 {
     }
 }
 
+impl ops::Div<f32> for V3 {
+    type Output = V3;
+    fn div(self, rh : f32) -> V3 {
+        self * (1.0 / rh)
+    }
+}
+
+impl ops::Neg for V3 {
+    type Output = V3;
+    fn neg(self) -> V3 {
+        V3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl ops::AddAssign<V3> for V3 {
+    fn add_assign(&mut self, rh : V3) {
+        *self = *self + rh;
+    }
+}
+
+impl ops::SubAssign<V3> for V3 {
+    fn sub_assign(&mut self, rh : V3) {
+        *self = *self - rh;
+    }
+}
+
+impl ops::MulAssign<f32> for V3 {
+    fn mul_assign(&mut self, rh : f32) {
+        *self = *self * rh;
+    }
+}
+
+impl ops::DivAssign<f32> for V3 {
+    fn div_assign(&mut self, rh : f32) {
+        *self = *self / rh;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct V2 {
+    pub x : f32,
+    pub y : f32,
+}
+
+impl V2 {
+    pub fn new(x : f32, y : f32) -> Self {
+        V2 {x, y}
+    }
+
+    pub fn zero() -> Self {
+        Self::new(0.0, 0.0)
+    }
+
+    pub fn dot(&self, rh : V2) -> f32 {
+        self.x * rh.x + self.y * rh.y
+    }
+
+    // z component of the 3d cross product, i.e. the signed parallelogram area
+    pub fn cross(&self, rh : V2) -> f32 {
+        self.x * rh.y - self.y * rh.x
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        f32::sqrt(self.dot(*self))
+    }
+
+    pub fn normalized(&self) -> V2 {
+        *self / self.magnitude()
+    }
+}
+
+impl ops::Add<V2> for V2 {
+    type Output = V2;
+    fn add(self, rh : V2) -> V2 {
+        V2::new(self.x + rh.x, self.y + rh.y)
+    }
+}
+
+impl ops::Sub<V2> for V2 {
+    type Output = V2;
+    fn sub(self, rh : V2) -> V2 {
+        V2::new(self.x - rh.x, self.y - rh.y)
+    }
+}
+
+impl ops::Mul<f32> for V2 {
+    type Output = V2;
+    fn mul(self, rh : f32) -> V2 {
+        V2::new(self.x * rh, self.y * rh)
+    }
+}
+
+impl ops::Mul<V2> for f32 {
+    type Output = V2;
+    fn mul(self, rh : V2) -> V2 {
+        rh * self
+    }
+}
+
+impl ops::Div<f32> for V2 {
+    type Output = V2;
+    fn div(self, rh : f32) -> V2 {
+        self * (1.0 / rh)
+    }
+}
+
+impl ops::Neg for V2 {
+    type Output = V2;
+    fn neg(self) -> V2 {
+        V2::new(-self.x, -self.y)
+    }
+}
+
+// homogeneous vector, w = 1 for points and w = 0 for directions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct V4 {
+    pub x : f32,
+    pub y : f32,
+    pub z : f32,
+    pub w : f32,
+}
+
+impl V4 {
+    pub fn new(x : f32, y : f32, z : f32, w : f32) -> Self {
+        V4 {x, y, z, w}
+    }
+
+    pub fn zero() -> Self {
+        Self::new(0.0, 0.0, 0.0, 0.0)
+    }
+
+    pub fn from_v3(v : V3, w : f32) -> Self {
+        Self::new(v.x, v.y, v.z, w)
+    }
+
+    pub fn xyz(&self) -> V3 {
+        V3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(&self, rh : V4) -> f32 {
+        self.x * rh.x + self.y * rh.y + self.z * rh.z + self.w * rh.w
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        f32::sqrt(self.dot(*self))
+    }
+}
+
+impl ops::Add<V4> for V4 {
+    type Output = V4;
+    fn add(self, rh : V4) -> V4 {
+        V4::new(self.x + rh.x, self.y + rh.y, self.z + rh.z, self.w + rh.w)
+    }
+}
+
+impl ops::Sub<V4> for V4 {
+    type Output = V4;
+    fn sub(self, rh : V4) -> V4 {
+        V4::new(self.x - rh.x, self.y - rh.y, self.z - rh.z, self.w - rh.w)
+    }
+}
+
+impl ops::Mul<f32> for V4 {
+    type Output = V4;
+    fn mul(self, rh : f32) -> V4 {
+        V4::new(self.x * rh, self.y * rh, self.z * rh, self.w * rh)
+    }
+}
+
+impl ops::Mul<V4> for f32 {
+    type Output = V4;
+    fn mul(self, rh : V4) -> V4 {
+        rh * self
+    }
+}
+
+impl ops::Div<f32> for V4 {
+    type Output = V4;
+    fn div(self, rh : f32) -> V4 {
+        self * (1.0 / rh)
+    }
+}
+
+impl ops::Neg for V4 {
+    type Output = V4;
+    fn neg(self) -> V4 {
+        V4::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : V3, b : V3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn cross_is_right_handed() {
+        let x = V3::new(1.0, 0.0, 0.0);
+        let y = V3::new(0.0, 1.0, 0.0);
+        assert!(close(x.cross(y), V3::new(0.0, 0.0, 1.0)));
+        let a = V3::new(1.0, 2.0, 3.0);
+        let b = V3::new(-2.0, 0.5, 4.0);
+        let c = a.cross(b);
+        assert!(f32::abs(c.dot(a)) < 1e-5 && f32::abs(c.dot(b)) < 1e-5);
+        assert!(close(c, V3::new(6.5, -10.0, 4.5)));
+    }
+
+    #[test]
+    fn reflect_flips_normal_component() {
+        let n = V3::new(0.0, 1.0, 0.0);
+        let d = V3::new(1.0, -1.0, 0.0).normalized();
+        assert!(close(d.reflect(n), V3::new(1.0, 1.0, 0.0).normalized()));
+    }
+
+    #[test]
+    fn refract_obeys_snell() {
+        let n = V3::new(0.0, 1.0, 0.0);
+        let d = V3::new(1.0, -1.0, 0.0).normalized();
+        let eta = 1.0 / 1.5;
+        let t = d.refract(n, eta).unwrap();
+        let sin_i = d.cross(n).magnitude();
+        let sin_t = t.cross(n).magnitude();
+        assert!(f32::abs(sin_i * eta - sin_t) < 1e-5);
+        assert!(f32::abs(t.magnitude() - 1.0) < 1e-5);
+        // going from glass into air at 45 degrees is past the critical angle
+        assert!(d.refract(n, 1.5).is_none());
+    }
+
+    #[test]
+    fn orthonormal_basis_is_orthonormal() {
+        for n in [V3::new(0.0, 0.0, 1.0), V3::new(0.0, 0.0, -1.0), V3::new(1.0, 2.0, -3.0).normalized()] {
+            let (t, b) = n.orthonormal_basis();
+            assert!(f32::abs(t.magnitude() - 1.0) < 1e-5);
+            assert!(f32::abs(b.magnitude() - 1.0) < 1e-5);
+            assert!(f32::abs(t.dot(n)) < 1e-5 && f32::abs(b.dot(n)) < 1e-5 && f32::abs(t.dot(b)) < 1e-5);
+            assert!(close(t.cross(b), n));
+        }
+    }
+}
+
 